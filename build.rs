@@ -1,31 +1,13 @@
-use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-use flatbuffers::{FlatBufferBuilder, WIPOffset};
-use serde::Deserialize;
-
-#[derive(Deserialize, Debug)]
-struct TomlConfig {
-    #[serde(rename = "static", default)]
-    static_styles: HashMap<String, String>,
-    #[serde(default)]
-    dynamic: HashMap<String, HashMap<String, String>>,
-    #[serde(default)]
-    generators: HashMap<String, GeneratorConfig>,
-}
-
-#[derive(Deserialize, Debug, Clone)]
-struct GeneratorConfig {
-    multiplier: f32,
-    unit: String,
-}
-
-#[derive(Debug, Clone)]
-struct StyleRecord {
-    name: String,
-    css: String,
-}
+#[path = "src/compiler.rs"]
+mod compiler;
+#[path = "src/resolver.rs"]
+#[allow(dead_code)]
+mod resolver;
+#[path = "src/validation.rs"]
+mod validation;
 
 fn main() {
     let fbs_file = "src/styles.fbs";
@@ -34,6 +16,10 @@ fn main() {
 
     println!("cargo:rerun-if-changed={}", fbs_file);
     println!("cargo:rerun-if-changed={}", toml_path);
+    println!("cargo:rerun-if-changed=src/compiler.rs");
+    println!("cargo:rerun-if-changed=src/validation.rs");
+    println!("cargo:rerun-if-changed=src/resolver.rs");
+    println!("cargo:rerun-if-changed=src/presets");
 
     flatc_rust::run(flatc_rust::Args {
         lang: "rust",
@@ -43,72 +29,17 @@ fn main() {
     })
     .expect("flatc schema compilation failed");
 
-    let toml_content = fs::read_to_string(toml_path).expect("Failed to read styles.toml");
-    let toml_data: TomlConfig = toml::from_str(&toml_content).expect("Failed to parse styles.toml");
-
-    let mut precompiled_styles = Vec::new();
-
-    for (name, css) in toml_data.static_styles {
-        precompiled_styles.push(StyleRecord { name, css });
+    let config = resolver::resolve(Path::new(toml_path)).unwrap_or_else(|error| panic!("\n{}", error));
+    for file in config.files() {
+        println!("cargo:rerun-if-changed={}", file.display());
     }
-
-    for (key, values) in toml_data.dynamic {
-        let parts: Vec<&str> = key.split('|').collect();
-        if parts.len() != 2 { continue; }
-        let prefix = parts[0];
-        let property = parts[1];
-        for (suffix, value) in values {
-            let name = format!("{}-{}", prefix, suffix);
-            let css = format!("{}: {};", property, value);
-            precompiled_styles.push(StyleRecord { name, css });
-        }
+    let buf = compiler::compile(&config).unwrap_or_else(|error| panic!("\n{}", error));
+    // The output is reproducible, so an unchanged styles.bin is left untouched.
+    if fs::read("styles.bin").ok().as_deref() != Some(buf.as_slice()) {
+        fs::write("styles.bin", &buf).expect("Failed to write styles.bin");
     }
-
-    precompiled_styles.sort_by(|a, b| a.name.cmp(&b.name));
-
-    let mut builder = FlatBufferBuilder::new();
-
-    let mut style_offsets = Vec::new();
-    for style in &precompiled_styles {
-        let name_offset = builder.create_string(&style.name);
-        let css_offset = builder.create_string(&style.css);
-        
-        let table_wip = builder.start_table();
-        builder.push_slot(4, name_offset, WIPOffset::new(0));
-        builder.push_slot(6, css_offset, WIPOffset::new(0));
-        let style_offset = builder.end_table(table_wip);
-        style_offsets.push(style_offset);
-    }
-    let styles_vec = builder.create_vector(&style_offsets);
-
-    let mut generator_offsets = Vec::new();
-    for (key, config) in toml_data.generators {
-        let parts: Vec<&str> = key.split('|').collect();
-        if parts.len() != 2 { continue; }
-        
-        let prefix_offset = builder.create_string(parts[0]);
-        let property_offset = builder.create_string(parts[1]);
-        let unit_offset = builder.create_string(&config.unit);
-
-        let table_wip = builder.start_table();
-        builder.push_slot(4, prefix_offset, WIPOffset::new(0));
-        builder.push_slot(6, property_offset, WIPOffset::new(0));
-        builder.push_slot(8, config.multiplier, 0.0f32);
-        builder.push_slot(10, unit_offset, WIPOffset::new(0));
-        let gen_offset = builder.end_table(table_wip);
-        generator_offsets.push(gen_offset);
-    }
-    let generators_vec = builder.create_vector(&generator_offsets);
-
-    let table_wip = builder.start_table();
-    builder.push_slot(4, styles_vec, WIPOffset::new(0));
-    builder.push_slot(6, generators_vec, WIPOffset::new(0));
-    let config_root = builder.end_table(table_wip);
-
-    builder.finish(config_root, None);
-
-    let buf = builder.finished_data();
-    fs::write("styles.bin", buf).expect("Failed to write styles.bin");
+    // Embedded in the executable as the config used when none is found.
+    fs::write(Path::new(&out_dir).join("styles.bin"), &buf).expect("Failed to write styles.bin");
 
     println!("✅ Successfully generated styles.bin from styles.toml");
 }
@@ -0,0 +1,73 @@
+//! Command-line arguments. Without a command, dx compiles styles.toml and
+//! watches src/ for class names.
+
+use std::path::{Path, PathBuf};
+
+use colored::Colorize;
+
+use crate::inspect::Inspect;
+use crate::{resolver, utils};
+
+pub enum Command {
+    Watch,
+    /// `dx config --resolved`: prints the merged styles.toml.
+    ConfigResolved,
+    /// `dx inspect`: shows what is in styles.bin.
+    Inspect(Inspect),
+}
+
+pub struct Args {
+    pub command: Command,
+    /// The styles.toml or styles.bin given with `--config`, instead of the
+    /// one found from the current directory.
+    pub config: Option<PathBuf>,
+}
+
+const USAGE: &str = "Usage:
+    dx [--config <path>]                      Watch src/ and write styles.css
+    dx config --resolved [--config <path>]    Print styles.toml merged with everything it extends
+    dx inspect [--config <path>]              Print the schema version, source hash and entries of styles.bin
+    dx inspect <class> [--config <path>]      Print the entry a class matches and its CSS
+    dx inspect --toml [--config <path>]       Print styles.bin decompiled to a styles.toml
+
+Options:
+    --config <path>    The styles.toml or styles.bin to use. By default, the nearest
+                       one in the current directory or above it (up to the git
+                       repository root), or the styles built into dx.";
+
+pub fn parse(args: &[String]) -> Result<Args, String> {
+    let mut config = None;
+    let mut words = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--config" {
+            let path = iter.next().ok_or_else(|| format!("--config needs a path\n\n{}", USAGE))?;
+            config = Some(PathBuf::from(path));
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            config = Some(PathBuf::from(path));
+        } else {
+            words.push(arg.as_str());
+        }
+    }
+    let command = match words.as_slice() {
+        [] => Command::Watch,
+        ["config", "--resolved"] => Command::ConfigResolved,
+        ["inspect"] => Command::Inspect(Inspect::Summary),
+        ["inspect", "--toml"] => Command::Inspect(Inspect::Toml),
+        ["inspect", class] => Command::Inspect(Inspect::Class(class.to_string())),
+        _ => return Err(format!("Unknown arguments: {}\n\n{}", args.join(" "), USAGE)),
+    };
+    Ok(Args { command, config })
+}
+
+/// Prints the resolved config, with the file or preset each entry came from.
+pub fn print_resolved_config(config_path: Option<&Path>) {
+    let Some(config_path) = config_path.filter(|path| utils::is_toml_file(path)) else {
+        println!("{} No styles.toml found to resolve.", "Error:".red());
+        return;
+    };
+    match resolver::resolve(config_path) {
+        Ok(config) => print!("{}", config.to_annotated_toml()),
+        Err(e) => println!("{} Failed to resolve {}:\n{}", "Error:".red(), config_path.display(), e),
+    }
+}
@@ -0,0 +1,238 @@
+/// How a color utility with an opacity modifier (e.g. bg-red-500/50) is written.
+#[derive(Clone, Copy, PartialEq)]
+pub enum OpacityStrategy {
+    /// color-mix(in oklab, <color> 50%, transparent); works for any color value.
+    ColorMix,
+    /// rgba(r, g, b, a); falls back to color-mix for values it can't convert.
+    Rgba,
+}
+
+struct Rgba {
+    r: f64,
+    g: f64,
+    b: f64,
+    a: f64,
+}
+
+/// Applies an alpha between 0 and 1 to a CSS color value. The rgba strategy
+/// converts the literal value, i.e. the color with any theme var() resolved.
+pub fn with_opacity(color: &str, literal: &str, alpha: f64, strategy: OpacityStrategy) -> String {
+    if strategy == OpacityStrategy::Rgba
+        && let Some(rgba) = parse_color(literal)
+    {
+        return format!(
+            "rgba({}, {}, {}, {})",
+            rgba.r.round(),
+            rgba.g.round(),
+            rgba.b.round(),
+            format_number(rgba.a * alpha)
+        );
+    }
+    format!("color-mix(in oklab, {} {}%, transparent)", color, format_number(alpha * 100.0))
+}
+
+/// Parses an opacity modifier: "50" is a percentage, "[0.37]" or "[37%]" an
+/// arbitrary alpha.
+pub fn parse_alpha(modifier: &str) -> Option<f64> {
+    let alpha = if let Some(arbitrary) = modifier.strip_prefix('[').and_then(|m| m.strip_suffix(']')) {
+        match arbitrary.strip_suffix('%') {
+            Some(percentage) => percentage.parse::<f64>().ok()? / 100.0,
+            None => arbitrary.parse::<f64>().ok()?,
+        }
+    } else {
+        modifier.parse::<f64>().ok()? / 100.0
+    };
+    (0.0..=1.0).contains(&alpha).then_some(alpha)
+}
+
+fn parse_color(color: &str) -> Option<Rgba> {
+    let color = color.trim();
+    if let Some(hex) = color.strip_prefix('#') {
+        return parse_hex(hex);
+    }
+
+    let (function, arguments) = color.strip_suffix(')')?.split_once('(')?;
+    let (channels, alpha) = match arguments.split_once('/') {
+        Some((channels, alpha)) => (channels, Some(alpha.trim())),
+        None => (arguments, None),
+    };
+    let mut channels: Vec<&str> = channels
+        .split(|ch: char| ch == ',' || ch.is_whitespace())
+        .filter(|channel| !channel.is_empty())
+        .collect();
+    // Legacy comma syntax carries the alpha as a fourth channel.
+    let alpha = match (alpha, channels.len()) {
+        (Some(alpha), 3) => parse_channel(alpha, 1.0)?,
+        (None, 4) => parse_channel(channels.pop()?, 1.0)?,
+        (None, 3) => 1.0,
+        _ => return None,
+    };
+
+    let rgba = match function.trim().to_ascii_lowercase().as_str() {
+        "rgb" | "rgba" => Rgba {
+            r: parse_channel(channels[0], 255.0)?,
+            g: parse_channel(channels[1], 255.0)?,
+            b: parse_channel(channels[2], 255.0)?,
+            a: alpha,
+        },
+        "hsl" | "hsla" => {
+            let hue = parse_hue(channels[0])?;
+            let saturation = parse_channel(channels[1], 1.0)?;
+            let lightness = parse_channel(channels[2], 1.0)?;
+            hsl_to_rgba(hue, saturation, lightness, alpha)
+        }
+        "oklch" => {
+            let lightness = parse_channel(channels[0], 1.0)?;
+            let chroma = parse_channel(channels[1], 0.4)?;
+            let hue = parse_hue(channels[2])?;
+            oklch_to_rgba(lightness, chroma, hue, alpha)
+        }
+        _ => return None,
+    };
+    Some(rgba)
+}
+
+fn parse_hex(hex: &str) -> Option<Rgba> {
+    if !hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digits: Vec<u8> = match hex.len() {
+        3 | 4 => hex.chars().map(|ch| u8::from_str_radix(&format!("{0}{0}", ch), 16)).collect::<Result<_, _>>().ok()?,
+        6 | 8 => (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&hex[index..index + 2], 16))
+            .collect::<Result<_, _>>()
+            .ok()?,
+        _ => return None,
+    };
+    Some(Rgba {
+        r: digits[0] as f64,
+        g: digits[1] as f64,
+        b: digits[2] as f64,
+        a: digits.get(3).map_or(1.0, |alpha| *alpha as f64 / 255.0),
+    })
+}
+
+/// Parses a number or a percentage, where 100% maps to `full`.
+fn parse_channel(channel: &str, full: f64) -> Option<f64> {
+    match channel.strip_suffix('%') {
+        Some(percentage) => Some(percentage.parse::<f64>().ok()? / 100.0 * full),
+        None => channel.parse::<f64>().ok(),
+    }
+}
+
+fn parse_hue(hue: &str) -> Option<f64> {
+    hue.strip_suffix("deg").unwrap_or(hue).parse::<f64>().ok()
+}
+
+fn hsl_to_rgba(hue: f64, saturation: f64, lightness: f64, alpha: f64) -> Rgba {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    Rgba {
+        r: (r + m) * 255.0,
+        g: (g + m) * 255.0,
+        b: (b + m) * 255.0,
+        a: alpha,
+    }
+}
+
+fn oklch_to_rgba(lightness: f64, chroma: f64, hue: f64, alpha: f64) -> Rgba {
+    let (a, b) = (chroma * hue.to_radians().cos(), chroma * hue.to_radians().sin());
+    let l = (lightness + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m = (lightness - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s = (lightness - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+
+    let to_srgb = |linear: f64| {
+        let encoded = if linear <= 0.0031308 {
+            12.92 * linear
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        };
+        (encoded * 255.0).clamp(0.0, 255.0)
+    };
+    Rgba {
+        r: to_srgb(4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s),
+        g: to_srgb(-1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s),
+        b: to_srgb(-0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s),
+        a: alpha,
+    }
+}
+
+fn format_number(value: f64) -> String {
+    format!("{}", (value * 1e4).round() / 1e4 + 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgba(color: &str, alpha: f64) -> String {
+        with_opacity(color, color, alpha, OpacityStrategy::Rgba)
+    }
+
+    #[test]
+    fn converts_hex_colors() {
+        assert_eq!(rgba("#ef4444", 0.5), "rgba(239, 68, 68, 0.5)");
+        assert_eq!(rgba("#F44", 1.0), "rgba(255, 68, 68, 1)");
+        assert_eq!(rgba("#ef444480", 0.5), "rgba(239, 68, 68, 0.251)");
+        assert_eq!(rgba("#ef44", 1.0), "rgba(238, 255, 68, 0.2667)");
+    }
+
+    #[test]
+    fn converts_hsl_colors() {
+        assert_eq!(rgba("hsl(120 100% 50%)", 0.5), "rgba(0, 255, 0, 0.5)");
+        assert_eq!(rgba("hsl(0deg 100% 50%)", 1.0), "rgba(255, 0, 0, 1)");
+        assert_eq!(rgba("hsl(240, 100%, 25%)", 1.0), "rgba(0, 0, 128, 1)");
+    }
+
+    #[test]
+    fn converts_oklch_colors() {
+        assert_eq!(rgba("oklch(62.8% 0.2577 29.23)", 0.5), "rgba(255, 0, 0, 0.5)");
+        assert_eq!(rgba("oklch(1 0 0)", 1.0), "rgba(255, 255, 255, 1)");
+        assert_eq!(rgba("oklch(0 0 0)", 1.0), "rgba(0, 0, 0, 1)");
+    }
+
+    #[test]
+    fn multiplies_the_alpha_of_the_color() {
+        assert_eq!(rgba("rgba(255, 0, 0, 0.5)", 0.5), "rgba(255, 0, 0, 0.25)");
+        assert_eq!(rgba("hsla(120, 100%, 50%, 0.5)", 0.5), "rgba(0, 255, 0, 0.25)");
+        assert_eq!(rgba("rgb(255 0 0 / 50%)", 0.5), "rgba(255, 0, 0, 0.25)");
+    }
+
+    #[test]
+    fn falls_back_to_color_mix_for_other_values() {
+        assert_eq!(rgba("currentColor", 0.5), "color-mix(in oklab, currentColor 50%, transparent)");
+        assert_eq!(rgba("rgb(1 2)", 0.5), "color-mix(in oklab, rgb(1 2) 50%, transparent)");
+        assert_eq!(rgba("#ef444", 0.5), "color-mix(in oklab, #ef444 50%, transparent)");
+        assert_eq!(
+            with_opacity("var(--color-red-500)", "#ef4444", 0.5, OpacityStrategy::ColorMix),
+            "color-mix(in oklab, var(--color-red-500) 50%, transparent)"
+        );
+    }
+
+    #[test]
+    fn parses_opacity_modifiers() {
+        assert_eq!(parse_alpha("50"), Some(0.5));
+        assert_eq!(parse_alpha("0"), Some(0.0));
+        assert_eq!(parse_alpha("100"), Some(1.0));
+        assert_eq!(parse_alpha("[0.37]"), Some(0.37));
+        assert_eq!(parse_alpha("[37%]"), Some(0.37));
+    }
+
+    #[test]
+    fn rejects_opacity_modifiers_out_of_range() {
+        for modifier in ["150", "-10", "[1.5]", "[150%]", "[]", "abc"] {
+            assert_eq!(parse_alpha(modifier), None, "{}", modifier);
+        }
+    }
+}
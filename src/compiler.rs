@@ -0,0 +1,1057 @@
+//! Compiles styles.toml into the FlatBuffers buffer stored in styles.bin. Used
+//! by build.rs and by the watcher to reload the config without rebuilding.
+
+use std::collections::{BTreeMap, HashMap};
+
+use flatbuffers::{FlatBufferBuilder, WIPOffset};
+use indexmap::IndexMap;
+use serde::Deserialize;
+
+use crate::resolver::ResolvedConfig;
+use crate::validation::{self, Diagnostic};
+
+/// The file_identifier declared in styles.fbs, written at the start of every
+/// styles.bin.
+pub const FILE_IDENTIFIER: &str = "DXST";
+
+/// The layout version of styles.bin. Bump it whenever a change to styles.fbs
+/// or to what the compiler writes would be misread by an older engine.
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Deserialize, Debug)]
+struct TomlConfig {
+    // Entries keep their order in styles.toml, which decides which rule wins.
+    #[serde(rename = "static", default)]
+    static_styles: IndexMap<String, StaticStyle>,
+    #[serde(default)]
+    dynamic: IndexMap<String, IndexMap<String, String>>,
+    #[serde(default)]
+    generators: IndexMap<String, GeneratorConfig>,
+    #[serde(default)]
+    variants: IndexMap<String, String>,
+    #[serde(default)]
+    breakpoints: IndexMap<String, String>,
+    #[serde(default)]
+    dark_mode: DarkModeConfig,
+    #[serde(default)]
+    theme: toml::Table,
+    #[serde(default)]
+    color_opacity: ColorOpacityConfig,
+    #[serde(default)]
+    css_variables: CssVariablesConfig,
+    #[serde(default)]
+    shortcuts: IndexMap<String, String>,
+    #[serde(default)]
+    preflight: PreflightConfig,
+    #[serde(default)]
+    base: IndexMap<String, StaticStyle>,
+    #[serde(default)]
+    keyframes: IndexMap<String, IndexMap<String, StaticStyle>>,
+}
+
+// A [static] entry: either a CSS string or a table of property = value declarations.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum StaticStyle {
+    Css(String),
+    Declarations(IndexMap<String, String>),
+}
+
+impl StaticStyle {
+    fn into_css(self) -> String {
+        match self {
+            StaticStyle::Css(css) => css,
+            StaticStyle::Declarations(declarations) => {
+                // In the order written, so later declarations override earlier ones.
+                declarations
+                    .iter()
+                    .map(|(property, value)| format!("{}: {};", property, value))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct GeneratorConfig {
+    #[serde(default)]
+    multiplier: Option<TokenNumber>,
+    #[serde(default)]
+    unit: String,
+    #[serde(default)]
+    palette: Option<String>,
+    #[serde(default)]
+    negative: bool,
+    #[serde(default)]
+    fraction: bool,
+    value: Option<String>,
+    #[serde(rename = "type", default)]
+    input: InputType,
+    min: Option<f32>,
+    max: Option<f32>,
+    step: Option<f32>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum InputType {
+    #[default]
+    Number,
+    Integer,
+    Percentage,
+    Fraction,
+}
+
+// A number that may also be written as a theme token reference, e.g. "{spacing}".
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum TokenNumber {
+    Number(f32),
+    Token(String),
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum DarkModeStrategy {
+    #[default]
+    Media,
+    Class,
+    Attribute,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct DarkModeConfig {
+    #[serde(default)]
+    strategy: DarkModeStrategy,
+    selector: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+enum OpacityStrategy {
+    #[default]
+    ColorMix,
+    Rgba,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct ColorOpacityConfig {
+    #[serde(default)]
+    strategy: OpacityStrategy,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct CssVariablesConfig {
+    #[serde(default = "default_true")]
+    enabled: bool,
+    #[serde(default)]
+    emit_all: bool,
+}
+
+impl Default for CssVariablesConfig {
+    fn default() -> Self {
+        Self { enabled: true, emit_all: false }
+    }
+}
+
+// [preflight]: the built-in reset, with per-selector overrides merged into it.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct PreflightConfig {
+    #[serde(default = "default_true")]
+    enabled: bool,
+    #[serde(default)]
+    overrides: IndexMap<String, IndexMap<String, String>>,
+}
+
+impl Default for PreflightConfig {
+    fn default() -> Self {
+        Self { enabled: true, overrides: IndexMap::new() }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// The flattened [theme] tokens. `resolved` holds the literal value of every
+/// token, `raw` the value as written (with references to other tokens).
+struct Theme {
+    resolved: HashMap<String, String>,
+    raw: HashMap<String, String>,
+    use_variables: bool,
+}
+
+#[derive(Debug, Clone)]
+struct StyleRecord {
+    name: String,
+    css: String,
+    order: u32,
+}
+
+/// What a prefix in the trie resolves to, in the order entries sharing a
+/// prefix are tried. Mirrors MatchKind in styles.fbs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MatchKind {
+    Color = 0,
+    Dynamic = 1,
+    Generator = 2,
+}
+
+/// A node of the prefix trie, with its children by byte and the entries
+/// (kind and index into generators or dynamics) whose prefix ends here.
+#[derive(Debug, Default)]
+struct TrieNode {
+    children: BTreeMap<u8, usize>,
+    matches: Vec<(MatchKind, u32)>,
+}
+
+/// Compiles a resolved styles.toml into a styles.bin buffer, or describes
+/// what is wrong with it as rendered diagnostics.
+pub fn compile(config: &ResolvedConfig) -> Result<Vec<u8>, String> {
+    let (toml_data, name, text) = match config.standalone_source() {
+        Some(source) => {
+            let toml_data = toml::from_str::<TomlConfig>(&source.text)
+                .map_err(|error| vec![Diagnostic::new(error.message(), error.span())]);
+            (toml_data, source.name.as_str(), source.text.as_str())
+        }
+        None => {
+            // Entries are replaced as a whole, so each source can be checked
+            // on its own, with errors pointing at the file that introduced them.
+            for source in &config.sources {
+                if let Err(error) = toml::from_str::<TomlConfig>(&source.text) {
+                    let diagnostics = [Diagnostic::new(error.message(), error.span())];
+                    return Err(validation::render(&diagnostics, &source.name, &source.text));
+                }
+            }
+            let toml_data = toml::Value::Table(config.table.clone())
+                .try_into::<TomlConfig>()
+                .map_err(|error| vec![Diagnostic::new(error.message(), None)]);
+            (toml_data, "the resolved config", "")
+        }
+    };
+    let source_hash = source_hash(config);
+    toml_data
+        .and_then(|toml_data| build(toml_data, source_hash).map_err(|message| vec![Diagnostic::new(message, None)]))
+        .map_err(|diagnostics| validation::render(&diagnostics, name, text))
+}
+
+/// A hash of the text of every source of the config, in merge order, stored
+/// in styles.bin so tooling can tell whether it is stale. FNV-1a, which gives
+/// the same value on every platform and Rust version.
+pub fn source_hash(config: &ResolvedConfig) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for source in &config.sources {
+        let length = (source.text.len() as u64).to_le_bytes();
+        for byte in length.iter().chain(source.text.as_bytes()) {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// Writes the config to a buffer. The output only depends on the config, so
+/// the same styles.toml always gives a byte-identical styles.bin.
+fn build(toml_data: TomlConfig, source_hash: u64) -> Result<Vec<u8>, String> {
+    let theme = resolve_theme(&toml_data.theme, toml_data.css_variables.enabled)?;
+
+    let mut precompiled_styles = Vec::new();
+    let mut dynamic_prefixes = Vec::new();
+    // Static entries, then dynamic entries, then generators, in file order.
+    let mut order = 0u32;
+
+    for (name, style) in toml_data.static_styles {
+        let css = style.into_css();
+        let css = theme.substitute(&css, &format!("[static] {}", name))?;
+        precompiled_styles.push(StyleRecord { name, css, order });
+        order += 1;
+    }
+
+    for (key, values) in toml_data.dynamic {
+        let parts: Vec<&str> = key.split('|').collect();
+        if parts.len() != 2 { continue; }
+        let prefix = parts[0];
+        let properties = split_properties(parts[1]);
+        dynamic_prefixes.push((prefix.to_string(), properties.clone(), order));
+        for (suffix, value) in values {
+            let name = format!("{}-{}", prefix, suffix);
+            let value = theme.substitute(&value, &format!("[dynamic] {}", name))?;
+            let css = properties
+                .iter()
+                .map(|property| format!("{}: {};", property, value))
+                .collect::<Vec<_>>()
+                .join(" ");
+            precompiled_styles.push(StyleRecord { name, css, order });
+        }
+        order += 1;
+    }
+
+    // Sorted by name (byte order, like the generated key comparison) so the
+    // engine can binary search them.
+    precompiled_styles.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut builder = FlatBufferBuilder::new();
+
+    let mut style_offsets = Vec::new();
+    for style in &precompiled_styles {
+        let name_offset = builder.create_string(&style.name);
+        let css_offset = builder.create_string(&style.css);
+        
+        let table_wip = builder.start_table();
+        builder.push_slot(4, name_offset, WIPOffset::new(0));
+        builder.push_slot(6, css_offset, WIPOffset::new(0));
+        builder.push_slot(8, style.order, 0u32);
+        let style_offset = builder.end_table(table_wip);
+        style_offsets.push(style_offset);
+    }
+    let styles_vec = builder.create_vector(&style_offsets);
+
+    let mut palettes: Vec<String> = toml_data
+        .generators
+        .values()
+        .filter_map(|config| config.palette.clone())
+        .collect();
+    palettes.sort();
+    palettes.dedup();
+
+    let mut palette_offsets = Vec::new();
+    for palette in &palettes {
+        let group = format!("{}.", palette);
+        let mut colors = Vec::new();
+        for path in theme.resolved.keys() {
+            if let Some(name) = path.strip_prefix(&group) {
+                let reference = format!("{{{}}}", path);
+                let value = theme.substitute(&reference, "[generators]")?;
+                let literal = theme.resolve(&reference, "[generators]")?;
+                colors.push((name.replace('.', "-"), value, literal));
+            }
+        }
+        if colors.is_empty() {
+            return Err(format!("Palette '{}' referenced in [generators] is not a [theme] group", palette));
+        }
+        colors.sort();
+
+        let mut color_offsets = Vec::new();
+        for (name, value, literal) in &colors {
+            let name_offset = builder.create_string(name);
+            let value_offset = builder.create_string(value);
+            let literal_offset = (literal != value).then(|| builder.create_string(literal));
+
+            let table_wip = builder.start_table();
+            builder.push_slot(4, name_offset, WIPOffset::new(0));
+            builder.push_slot(6, value_offset, WIPOffset::new(0));
+            if let Some(literal_offset) = literal_offset {
+                builder.push_slot_always(8, literal_offset);
+            }
+            let color_offset = builder.end_table(table_wip);
+            color_offsets.push(color_offset);
+        }
+        let colors_vec = builder.create_vector(&color_offsets);
+        let name_offset = builder.create_string(palette);
+
+        let table_wip = builder.start_table();
+        builder.push_slot(4, name_offset, WIPOffset::new(0));
+        builder.push_slot(6, colors_vec, WIPOffset::new(0));
+        let palette_offset = builder.end_table(table_wip);
+        palette_offsets.push(palette_offset);
+    }
+    let palettes_vec = builder.create_vector(&palette_offsets);
+
+    let mut generator_offsets = Vec::new();
+    let mut prefixes = Vec::new();
+    for (key, config) in toml_data.generators {
+        let parts: Vec<&str> = key.split('|').collect();
+        if parts.len() != 2 { continue; }
+        
+        let context = format!("[generators] {}", key);
+        let mut unit = theme.resolve(&config.unit, &context)?;
+        let mut variable = None;
+        let multiplier = match &config.multiplier {
+            Some(TokenNumber::Number(multiplier)) => *multiplier,
+            Some(TokenNumber::Token(token)) => {
+                let value = theme.resolve(token, &context)?;
+                let (multiplier, token_unit) = split_number_unit(&value)
+                    .ok_or_else(|| format!("Multiplier '{}' of {} in styles.toml is not a number", value, context))?;
+                if multiplier <= 0.0 {
+                    return Err(format!("Multiplier '{}' of {} in styles.toml must be positive", value, context));
+                }
+                if !token_unit.is_empty() {
+                    if !unit.is_empty() {
+                        return Err(format!(
+                            "{} in styles.toml sets a unit but its multiplier '{}' already has one",
+                            context, value
+                        ));
+                    }
+                    unit = token_unit.to_string();
+                }
+                // A multiplier that is exactly one token is emitted as calc(var(--token) * n).
+                if theme.use_variables
+                    && let [reference] = token_references(token).as_slice()
+                    && token.trim() == format!("{{{}}}", reference)
+                {
+                    variable = Some(token_variable(reference));
+                    // The unit of the token comes with the variable; a unitless
+                    // token needs the generator's own: calc(var(--token) * 4px).
+                    unit = theme.resolve(&config.unit, &context)?;
+                }
+                multiplier
+            }
+            None if config.palette.is_some() || config.value.is_some() => 0.0,
+            None => return Err(format!("{} in styles.toml needs a multiplier, a value template or a palette", context)),
+        };
+        let template = match config.value.as_deref() {
+            Some(template) => Some(theme.substitute_template(template, &context)?),
+            None => None,
+        };
+
+        let prefix_offset = builder.create_string(parts[0]);
+        let property_offsets: Vec<_> = split_properties(parts[1])
+            .iter()
+            .map(|property| builder.create_string(property))
+            .collect();
+        let properties_offset = builder.create_vector(&property_offsets);
+        let unit_offset = builder.create_string(&unit);
+        let palette_offset = config.palette.as_deref().map(|palette| builder.create_string(palette));
+        let variable_offset = variable.as_deref().map(|variable| builder.create_string(variable));
+        let template_offset = template.as_deref().map(|template| builder.create_string(template));
+
+        let table_wip = builder.start_table();
+        builder.push_slot(4, prefix_offset, WIPOffset::new(0));
+        builder.push_slot(8, multiplier, 0.0f32);
+        builder.push_slot(10, unit_offset, WIPOffset::new(0));
+        builder.push_slot(12, config.negative, false);
+        builder.push_slot(14, config.fraction, false);
+        if let Some(palette_offset) = palette_offset {
+            builder.push_slot_always(16, palette_offset);
+        }
+        if let Some(variable_offset) = variable_offset {
+            builder.push_slot_always(18, variable_offset);
+        }
+        builder.push_slot(20, properties_offset, WIPOffset::new(0));
+        if let Some(template_offset) = template_offset {
+            builder.push_slot_always(22, template_offset);
+        }
+        builder.push_slot(24, config.input as i8, 0i8);
+        if let Some(min) = config.min {
+            builder.push_slot_always(26, min);
+        }
+        if let Some(max) = config.max {
+            builder.push_slot_always(28, max);
+        }
+        builder.push_slot(30, config.step.unwrap_or(0.0), 0.0f32);
+        builder.push_slot(32, order, 0u32);
+        let gen_offset = builder.end_table(table_wip);
+        let kind = if config.palette.is_some() { MatchKind::Color } else { MatchKind::Generator };
+        prefixes.push((parts[0].to_string(), kind, generator_offsets.len() as u32));
+        generator_offsets.push(gen_offset);
+        order += 1;
+    }
+    let generators_vec = builder.create_vector(&generator_offsets);
+
+    let mut variants: Vec<(String, String)> = toml_data.variants.into_iter().collect();
+    variants.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut variant_offsets = Vec::new();
+    for (name, selector) in &variants {
+        if !selector.contains('&') {
+            return Err(format!("Variant '{}' in styles.toml must have a '&' where the class goes, e.g. \"&:hover\"", name));
+        }
+        let name_offset = builder.create_string(name);
+        let selector_offset = builder.create_string(selector);
+
+        let table_wip = builder.start_table();
+        builder.push_slot(4, name_offset, WIPOffset::new(0));
+        builder.push_slot(6, selector_offset, WIPOffset::new(0));
+        let variant_offset = builder.end_table(table_wip);
+        variant_offsets.push(variant_offset);
+    }
+    let variants_vec = builder.create_vector(&variant_offsets);
+
+    let mut breakpoints = Vec::new();
+    for (name, min_width) in toml_data.breakpoints {
+        breakpoints.push((css_length_in_px(&min_width)?, name, min_width));
+    }
+    breakpoints.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+
+    let mut breakpoint_offsets = Vec::new();
+    for (_, name, min_width) in &breakpoints {
+        let name_offset = builder.create_string(name);
+        let min_width_offset = builder.create_string(min_width);
+
+        let table_wip = builder.start_table();
+        builder.push_slot(4, name_offset, WIPOffset::new(0));
+        builder.push_slot(6, min_width_offset, WIPOffset::new(0));
+        let breakpoint_offset = builder.end_table(table_wip);
+        breakpoint_offsets.push(breakpoint_offset);
+    }
+    let breakpoints_vec = builder.create_vector(&breakpoint_offsets);
+
+    // Kept in file order: the index of an entry in the trie is its position.
+    let mut dynamic_offsets = Vec::new();
+    for (prefix, properties, order) in &dynamic_prefixes {
+        let prefix_offset = builder.create_string(prefix);
+        let property_offsets: Vec<_> = properties.iter().map(|property| builder.create_string(property)).collect();
+        let properties_offset = builder.create_vector(&property_offsets);
+
+        let table_wip = builder.start_table();
+        builder.push_slot(4, prefix_offset, WIPOffset::new(0));
+        builder.push_slot(8, properties_offset, WIPOffset::new(0));
+        builder.push_slot(10, *order, 0u32);
+        let dynamic_offset = builder.end_table(table_wip);
+        dynamic_offsets.push(dynamic_offset);
+    }
+    let dynamics_vec = builder.create_vector(&dynamic_offsets);
+
+    for (index, (prefix, _, _)) in dynamic_prefixes.iter().enumerate() {
+        prefixes.push((prefix.clone(), MatchKind::Dynamic, index as u32));
+    }
+    let mut node_offsets = Vec::new();
+    for node in prefix_trie(&prefixes) {
+        let bytes: Vec<u8> = node.children.keys().copied().collect();
+        let children: Vec<u32> = node.children.values().map(|&child| child as u32).collect();
+        let bytes_offset = builder.create_vector(&bytes);
+        let children_offset = builder.create_vector(&children);
+        let mut match_offsets = Vec::new();
+        for (kind, index) in node.matches {
+            let table_wip = builder.start_table();
+            builder.push_slot(4, kind as i8, 0i8);
+            builder.push_slot(6, index, 0u32);
+            match_offsets.push(builder.end_table(table_wip));
+        }
+        let matches_offset = builder.create_vector(&match_offsets);
+
+        let table_wip = builder.start_table();
+        builder.push_slot(4, bytes_offset, WIPOffset::new(0));
+        builder.push_slot(6, children_offset, WIPOffset::new(0));
+        builder.push_slot(8, matches_offset, WIPOffset::new(0));
+        node_offsets.push(builder.end_table(table_wip));
+    }
+    let trie_vec = builder.create_vector(&node_offsets);
+
+    let mut variables = theme.variables()?;
+    variables.sort();
+
+    let mut variable_offsets = Vec::new();
+    for (name, value) in &variables {
+        let name_offset = builder.create_string(name);
+        let value_offset = builder.create_string(value);
+
+        let table_wip = builder.start_table();
+        builder.push_slot(4, name_offset, WIPOffset::new(0));
+        builder.push_slot(6, value_offset, WIPOffset::new(0));
+        let variable_offset = builder.end_table(table_wip);
+        variable_offsets.push(variable_offset);
+    }
+    let variables_vec = builder.create_vector(&variable_offsets);
+
+    let mut base_rules = Vec::new();
+    if toml_data.preflight.enabled {
+        base_rules.extend(preflight(&theme, toml_data.preflight.overrides));
+    }
+    for (selector, style) in toml_data.base {
+        let css = style.into_css();
+        base_rules.push((selector, css));
+    }
+
+    let mut base_offsets = Vec::new();
+    for (selector, css) in &base_rules {
+        let css = theme.substitute(css, &format!("[base] {}", selector))?;
+        let selector_offset = builder.create_string(selector);
+        let css_offset = builder.create_string(&css);
+
+        let table_wip = builder.start_table();
+        builder.push_slot(4, selector_offset, WIPOffset::new(0));
+        builder.push_slot(6, css_offset, WIPOffset::new(0));
+        let base_offset = builder.end_table(table_wip);
+        base_offsets.push(base_offset);
+    }
+    let base_vec = builder.create_vector(&base_offsets);
+
+    let mut keyframes: Vec<(String, IndexMap<String, StaticStyle>)> = toml_data.keyframes.into_iter().collect();
+    keyframes.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut keyframes_offsets = Vec::new();
+    for (name, steps) in keyframes {
+        let mut step_offsets = Vec::new();
+        for (step, style) in steps {
+            let css = theme.substitute(&style.into_css(), &format!("[keyframes] {} {}", name, step))?;
+            let selector_offset = builder.create_string(&step);
+            let css_offset = builder.create_string(&css);
+
+            let table_wip = builder.start_table();
+            builder.push_slot(4, selector_offset, WIPOffset::new(0));
+            builder.push_slot(6, css_offset, WIPOffset::new(0));
+            let step_offset = builder.end_table(table_wip);
+            step_offsets.push(step_offset);
+        }
+        let steps_offset = builder.create_vector(&step_offsets);
+        let name_offset = builder.create_string(&name);
+
+        let table_wip = builder.start_table();
+        builder.push_slot(4, name_offset, WIPOffset::new(0));
+        builder.push_slot(6, steps_offset, WIPOffset::new(0));
+        let keyframes_offset = builder.end_table(table_wip);
+        keyframes_offsets.push(keyframes_offset);
+    }
+    let keyframes_vec = builder.create_vector(&keyframes_offsets);
+
+    let mut shortcut_names: Vec<&String> = toml_data.shortcuts.keys().collect();
+    shortcut_names.sort();
+
+    let mut shortcut_offsets = Vec::new();
+    for name in shortcut_names {
+        let classes = expand_shortcut(name, &toml_data.shortcuts, &mut Vec::new())?;
+        let class_offsets: Vec<_> = classes.iter().map(|class| builder.create_string(class)).collect();
+        let classes_offset = builder.create_vector(&class_offsets);
+        let name_offset = builder.create_string(name);
+
+        let table_wip = builder.start_table();
+        builder.push_slot(4, name_offset, WIPOffset::new(0));
+        builder.push_slot(6, classes_offset, WIPOffset::new(0));
+        let shortcut_offset = builder.end_table(table_wip);
+        shortcut_offsets.push(shortcut_offset);
+    }
+    let shortcuts_vec = builder.create_vector(&shortcut_offsets);
+
+    let dark_mode = toml_data.dark_mode;
+    let dark_selector = dark_mode.selector.unwrap_or_else(|| match dark_mode.strategy {
+        DarkModeStrategy::Media => String::new(),
+        DarkModeStrategy::Class => ".dark".to_string(),
+        DarkModeStrategy::Attribute => "[data-theme=dark]".to_string(),
+    });
+    let dark_selector_offset = builder.create_string(&dark_selector);
+    let table_wip = builder.start_table();
+    builder.push_slot(4, dark_mode.strategy as i8, 0i8);
+    builder.push_slot(6, dark_selector_offset, WIPOffset::new(0));
+    let dark_mode_offset = builder.end_table(table_wip);
+
+    let table_wip = builder.start_table();
+    builder.push_slot(4, styles_vec, WIPOffset::new(0));
+    builder.push_slot(6, generators_vec, WIPOffset::new(0));
+    builder.push_slot(8, variants_vec, WIPOffset::new(0));
+    builder.push_slot(10, breakpoints_vec, WIPOffset::new(0));
+    builder.push_slot(12, dark_mode_offset, WIPOffset::new(0));
+    builder.push_slot(14, dynamics_vec, WIPOffset::new(0));
+    builder.push_slot(16, palettes_vec, WIPOffset::new(0));
+    builder.push_slot(18, toml_data.color_opacity.strategy as i8, 0i8);
+    builder.push_slot(20, variables_vec, WIPOffset::new(0));
+    builder.push_slot(22, toml_data.css_variables.emit_all, false);
+    builder.push_slot(24, shortcuts_vec, WIPOffset::new(0));
+    builder.push_slot(26, base_vec, WIPOffset::new(0));
+    builder.push_slot(28, keyframes_vec, WIPOffset::new(0));
+    builder.push_slot(30, SCHEMA_VERSION, 0u32);
+    builder.push_slot(32, trie_vec, WIPOffset::new(0));
+    builder.push_slot(34, source_hash, 0u64);
+    let config_root = builder.end_table(table_wip);
+
+    builder.finish(config_root, Some(FILE_IDENTIFIER));
+
+    Ok(builder.finished_data().to_vec())
+}
+
+/// Expands a shortcut into its classes, recursively replacing any class that is
+/// itself a shortcut. Variants on a nested shortcut (e.g. "hover:btn-base") are
+/// prepended to each of its classes.
+fn expand_shortcut(
+    name: &str,
+    shortcuts: &IndexMap<String, String>,
+    stack: &mut Vec<String>,
+) -> Result<Vec<String>, String> {
+    if stack.iter().any(|visiting| visiting == name) {
+        stack.push(name.to_string());
+        return Err(format!("Cyclic shortcut in styles.toml: {}", stack.join(" -> ")));
+    }
+    stack.push(name.to_string());
+
+    let mut classes = Vec::new();
+    for class in shortcuts[name].split_whitespace() {
+        let (variants, utility) = match class.rfind(':') {
+            Some(index) if !class[index..].contains(']') => class.split_at(index + 1),
+            _ => ("", class),
+        };
+        if shortcuts.contains_key(utility) {
+            for nested in expand_shortcut(utility, shortcuts, stack)? {
+                classes.push(format!("{}{}", variants, nested));
+            }
+        } else {
+            classes.push(class.to_string());
+        }
+    }
+
+    stack.pop();
+    Ok(classes)
+}
+
+/// The built-in reset: box-sizing, no default margins or padding, borders in the
+/// theme's border color, and form controls inheriting the page font. Each
+/// override replaces or adds declarations of the rule with the same selector
+/// (or adds a rule); an empty value removes the declaration.
+fn preflight(theme: &Theme, overrides: IndexMap<String, IndexMap<String, String>>) -> Vec<(String, String)> {
+    let token_or = |path: &str, fallback: &str| {
+        if theme.resolved.contains_key(path) { format!("{{{}}}", path) } else { fallback.to_string() }
+    };
+    let border_color = token_or("colors.border", "currentColor");
+    let font_sans = token_or("fonts.sans", "ui-sans-serif, system-ui, sans-serif");
+    let font_mono = token_or("fonts.mono", "ui-monospace, monospace");
+
+    let mut rules: IndexMap<String, IndexMap<String, String>> = IndexMap::new();
+    let mut rule = |selector: &str, declarations: &[(&str, &str)]| {
+        let declarations = declarations
+            .iter()
+            .map(|(property, value)| (property.to_string(), value.to_string()))
+            .collect();
+        rules.insert(selector.to_string(), declarations);
+    };
+    rule(
+        "*, ::before, ::after, ::backdrop",
+        &[("box-sizing", "border-box"), ("margin", "0"), ("padding", "0"), ("border", &format!("0 solid {}", border_color))],
+    );
+    rule(
+        "html, :host",
+        &[("line-height", "1.5"), ("-webkit-text-size-adjust", "100%"), ("tab-size", "4"), ("font-family", &font_sans)],
+    );
+    rule("body", &[("line-height", "inherit")]);
+    rule("hr", &[("height", "0"), ("color", "inherit"), ("border-top-width", "1px")]);
+    rule("h1, h2, h3, h4, h5, h6", &[("font-size", "inherit"), ("font-weight", "inherit")]);
+    rule("a", &[("color", "inherit"), ("text-decoration", "inherit")]);
+    rule("b, strong", &[("font-weight", "bolder")]);
+    rule("code, kbd, samp, pre", &[("font-family", &font_mono), ("font-size", "1em")]);
+    rule("ol, ul, menu", &[("list-style", "none")]);
+    rule(
+        "img, svg, video, canvas, audio, iframe, embed, object",
+        &[("display", "block"), ("vertical-align", "middle")],
+    );
+    rule("img, video", &[("max-width", "100%"), ("height", "auto")]);
+    rule(
+        "button, input, select, optgroup, textarea, ::file-selector-button",
+        &[
+            ("font", "inherit"),
+            ("font-feature-settings", "inherit"),
+            ("font-variation-settings", "inherit"),
+            ("letter-spacing", "inherit"),
+            ("color", "inherit"),
+            ("background-color", "transparent"),
+        ],
+    );
+    rule("textarea", &[("resize", "vertical")]);
+    rule("[hidden]:where(:not([hidden=\"until-found\"]))", &[("display", "none !important")]);
+
+    for (selector, declarations) in overrides {
+        let rule = rules.entry(selector).or_default();
+        for (property, value) in declarations {
+            if value.is_empty() {
+                rule.shift_remove(&property);
+            } else {
+                rule.insert(property, value);
+            }
+        }
+    }
+
+    rules
+        .into_iter()
+        .filter(|(_, declarations)| !declarations.is_empty())
+        .map(|(selector, declarations)| {
+            let css = declarations
+                .iter()
+                .map(|(property, value)| format!("{}: {};", property, value))
+                .collect::<Vec<_>>()
+                .join(" ");
+            (selector, css)
+        })
+        .collect()
+}
+
+/// Splits the property part of a "prefix|property" key, which may list several
+/// comma-separated properties (e.g. "px|padding-left,padding-right").
+fn split_properties(properties: &str) -> Vec<String> {
+    properties
+        .split(',')
+        .map(str::trim)
+        .filter(|property| !property.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn css_length_in_px(value: &str) -> Result<f32, String> {
+    let value = value.trim();
+    let (number, scale) = if let Some(number) = value.strip_suffix("px") {
+        (number, 1.0)
+    } else if let Some(number) = value.strip_suffix("rem").or_else(|| value.strip_suffix("em")) {
+        (number, 16.0)
+    } else {
+        (value, 1.0)
+    };
+    number
+        .trim()
+        .parse::<f32>()
+        .map(|n| n * scale)
+        .map_err(|_| format!("Invalid breakpoint width '{}' in styles.toml", value))
+}
+
+/// Builds a trie over the "prefix-" of every generator and dynamic entry, so
+/// the engine finds the entries matching a class by walking it once. The root
+/// is node 0, and the entries of a node are sorted by kind, then file order.
+fn prefix_trie(prefixes: &[(String, MatchKind, u32)]) -> Vec<TrieNode> {
+    let mut nodes = vec![TrieNode::default()];
+    for (prefix, kind, index) in prefixes {
+        let mut node = 0;
+        for byte in prefix.bytes().chain([b'-']) {
+            node = match nodes[node].children.get(&byte) {
+                Some(&child) => child,
+                None => {
+                    nodes.push(TrieNode::default());
+                    let child = nodes.len() - 1;
+                    nodes[node].children.insert(byte, child);
+                    child
+                }
+            };
+        }
+        nodes[node].matches.push((*kind, *index));
+    }
+    for node in &mut nodes {
+        node.matches.sort();
+    }
+    nodes
+}
+
+/// Flattens the [theme] table into dotted token paths (e.g. "colors.slate.200")
+/// and resolves every token that references another one.
+fn resolve_theme(table: &toml::Table, use_variables: bool) -> Result<Theme, String> {
+    let mut raw = HashMap::new();
+    flatten_theme(table, "", &mut raw)?;
+
+    let mut resolved = HashMap::new();
+    let mut paths: Vec<&String> = raw.keys().collect();
+    paths.sort();
+    for path in paths {
+        resolve_token(path, &raw, &mut resolved, &mut Vec::new())?;
+    }
+    Ok(Theme { resolved, raw, use_variables })
+}
+
+impl Theme {
+    /// Replaces every "{group.token}" reference in a value with a var() of the
+    /// token's CSS variable, or with its literal value when variables are off.
+    fn substitute(&self, value: &str, context: &str) -> Result<String, String> {
+        if !self.use_variables {
+            return self.resolve(value, context);
+        }
+        let mut substituted = value.to_string();
+        for reference in token_references(value) {
+            if !self.resolved.contains_key(&reference) {
+                return Err(format!("Undefined theme token '{{{}}}' referenced in {}", reference, context));
+            }
+            let variable = format!("var({})", token_variable(&reference));
+            substituted = substituted.replace(&format!("{{{}}}", reference), &variable);
+        }
+        Ok(substituted)
+    }
+
+    /// Substitutes the tokens of a generator value template, keeping its "{n}"
+    /// placeholders.
+    fn substitute_template(&self, template: &str, context: &str) -> Result<String, String> {
+        let parts = template
+            .split("{n}")
+            .map(|part| self.substitute(part, context))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(parts.join("{n}"))
+    }
+
+    /// Replaces every "{group.token}" reference in a value with its literal value.
+    fn resolve(&self, value: &str, context: &str) -> Result<String, String> {
+        let mut substituted = value.to_string();
+        for reference in token_references(value) {
+            let Some(token_value) = self.resolved.get(&reference) else {
+                return Err(format!("Undefined theme token '{{{}}}' referenced in {}", reference, context));
+            };
+            substituted = substituted.replace(&format!("{{{}}}", reference), token_value);
+        }
+        Ok(substituted)
+    }
+
+    /// The CSS variable of every token, with references to other tokens kept as var().
+    fn variables(&self) -> Result<Vec<(String, String)>, String> {
+        if !self.use_variables {
+            return Ok(Vec::new());
+        }
+        self.raw
+            .iter()
+            .map(|(path, value)| Ok((token_variable(path), self.substitute(value, &format!("[theme] {}", path))?)))
+            .collect()
+    }
+}
+
+/// The CSS variable of a theme token: "colors.slate.200" -> "--color-slate-200",
+/// "spacing" -> "--spacing".
+pub fn token_variable(path: &str) -> String {
+    let (group, rest) = match path.split_once('.') {
+        Some((group, rest)) => (group, Some(rest)),
+        None => (path, None),
+    };
+    let group = match group {
+        "colors" => "color",
+        "radii" => "radius",
+        "fonts" => "font",
+        "shadows" => "shadow",
+        group => group,
+    };
+    match rest {
+        Some(rest) => format!("--{}-{}", group, rest.replace('.', "-")),
+        None => format!("--{}", group),
+    }
+}
+
+/// Splits a number with an optional unit, e.g. "0.25rem" -> (0.25, "rem").
+fn split_number_unit(value: &str) -> Option<(f32, &str)> {
+    let value = value.trim();
+    let split = value
+        .find(|ch: char| !(ch.is_ascii_digit() || matches!(ch, '.' | '-' | '+')))
+        .unwrap_or(value.len());
+    let number = value[..split].parse::<f32>().ok()?;
+    Some((number, &value[split..]))
+}
+
+fn flatten_theme(table: &toml::Table, prefix: &str, tokens: &mut HashMap<String, String>) -> Result<(), String> {
+    for (key, value) in table {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match value {
+            toml::Value::Table(group) => flatten_theme(group, &path, tokens)?,
+            toml::Value::String(value) => {
+                tokens.insert(path, value.clone());
+            }
+            toml::Value::Integer(value) => {
+                tokens.insert(path, value.to_string());
+            }
+            toml::Value::Float(value) => {
+                tokens.insert(path, value.to_string());
+            }
+            _ => return Err(format!("Theme token '{}' in styles.toml must be a string or a number", path)),
+        }
+    }
+    Ok(())
+}
+
+fn resolve_token(
+    path: &str,
+    raw: &HashMap<String, String>,
+    resolved: &mut HashMap<String, String>,
+    stack: &mut Vec<String>,
+) -> Result<String, String> {
+    if let Some(value) = resolved.get(path) {
+        return Ok(value.clone());
+    }
+    if stack.iter().any(|visiting| visiting == path) {
+        stack.push(path.to_string());
+        return Err(format!("Cyclic theme token reference in styles.toml: {}", stack.join(" -> ")));
+    }
+
+    let context = match stack.last() {
+        Some(parent) => format!("[theme] {}", parent),
+        None => format!("[theme] {}", path),
+    };
+    let Some(value) = raw.get(path) else {
+        return Err(format!("Undefined theme token '{{{}}}' referenced in {}", path, context));
+    };
+
+    stack.push(path.to_string());
+    let mut value = value.clone();
+    for reference in token_references(&value) {
+        let token_value = resolve_token(&reference, raw, resolved, stack)?;
+        value = value.replace(&format!("{{{}}}", reference), &token_value);
+    }
+    stack.pop();
+
+    resolved.insert(path.to_string(), value.clone());
+    Ok(value)
+}
+
+fn token_references(value: &str) -> Vec<String> {
+    let mut references = Vec::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else { break };
+        let reference = &after[..end];
+        let is_token = !reference.is_empty()
+            && reference.chars().all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '.' | '-' | '_'));
+        if is_token {
+            references.push(reference.to_string());
+            rest = &after[end + 1..];
+        } else {
+            rest = after;
+        }
+    }
+    references
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Walks a class through the trie and returns the matches of every node
+    /// it passes, shortest prefix first.
+    fn matches_along(nodes: &[TrieNode], class: &str) -> Vec<Vec<(MatchKind, u32)>> {
+        let mut found = Vec::new();
+        let mut node = 0;
+        for byte in class.bytes() {
+            let Some(&child) = nodes[node].children.get(&byte) else { break };
+            node = child;
+            if !nodes[node].matches.is_empty() {
+                found.push(nodes[node].matches.clone());
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn errors_in_layered_configs_point_at_their_file() {
+        let source = crate::resolver::Source {
+            name: "styles.toml".to_string(),
+            path: None,
+            text: "extends = [\"breakpoints\"]\n\n[static]\nflex = 3\n".to_string(),
+        };
+        let error = compile(&crate::resolver::resolve_source(source).unwrap()).unwrap_err();
+        assert!(error.contains("styles.toml:4:8"), "{}", error);
+    }
+
+    #[test]
+    fn prefix_trie_ends_every_prefix_with_a_dash() {
+        let nodes = prefix_trie(&[("rounded".to_string(), MatchKind::Dynamic, 0)]);
+        assert_eq!(matches_along(&nodes, "rounded-sm"), vec![vec![(MatchKind::Dynamic, 0)]]);
+        assert!(matches_along(&nodes, "rounded").is_empty());
+        assert!(matches_along(&nodes, "roundedx-sm").is_empty());
+    }
+
+    #[test]
+    fn prefix_trie_finds_every_prefix_of_a_class() {
+        let nodes = prefix_trie(&[
+            ("rounded-t".to_string(), MatchKind::Dynamic, 1),
+            ("rounded".to_string(), MatchKind::Dynamic, 0),
+        ]);
+        assert_eq!(
+            matches_along(&nodes, "rounded-t-[3px]"),
+            vec![vec![(MatchKind::Dynamic, 0)], vec![(MatchKind::Dynamic, 1)]]
+        );
+        assert_eq!(matches_along(&nodes, "rounded-[3px]"), vec![vec![(MatchKind::Dynamic, 0)]]);
+    }
+
+    #[test]
+    fn prefix_trie_orders_shared_prefixes_by_kind_then_file_order() {
+        let nodes = prefix_trie(&[
+            ("text".to_string(), MatchKind::Generator, 3),
+            ("text".to_string(), MatchKind::Dynamic, 0),
+            ("text".to_string(), MatchKind::Generator, 1),
+            ("text".to_string(), MatchKind::Color, 2),
+        ]);
+        assert_eq!(
+            matches_along(&nodes, "text-14"),
+            vec![vec![(MatchKind::Color, 2), (MatchKind::Dynamic, 0), (MatchKind::Generator, 1), (MatchKind::Generator, 3)]]
+        );
+    }
+}
@@ -1,62 +1,1274 @@
-use std::collections::HashMap;
-use std::fs;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+use colored::Colorize;
+
+use crate::color::{self, OpacityStrategy};
+use crate::{compiler, resolver, utils, validation};
 
 mod styles_generated {
-    #![allow(dead_code, unused_imports, unsafe_op_in_unsafe_fn)]
+    #![allow(dead_code, unused_imports, unsafe_op_in_unsafe_fn, clippy::all)]
     include!(concat!(env!("OUT_DIR"), "/styles_generated.rs"));
 }
-use styles_generated::style_schema;
+pub use styles_generated::style_schema;
+
+/// The styles.bin compiled from the crate's styles.toml by build.rs, used
+/// when no config is found for the project.
+const EMBEDDED_CONFIG: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/styles.bin"));
+
+/// Why a styles.bin could not be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file doesn't exist or can't be read.
+    Missing(io::Error),
+    /// The file isn't a styles.bin, or fails FlatBuffers verification.
+    Corrupt(String),
+    /// The file was written by a compiler with another schema version.
+    VersionMismatch { found: u32, expected: u32 },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Missing(error) => write!(f, "styles.bin could not be read: {}", error),
+            LoadError::Corrupt(reason) => write!(f, "styles.bin is corrupt: {}", reason),
+            LoadError::VersionMismatch { found, expected } => write!(
+                f,
+                "styles.bin has schema version {}, but this version of dx reads version {}",
+                found, expected
+            ),
+        }
+    }
+}
 
+impl std::error::Error for LoadError {}
+
+/// Reads a styles.bin in place: variants, palettes, theme variables,
+/// shortcuts and keyframes are looked up by binary search in the buffer.
 pub struct StyleEngine {
-    precompiled: HashMap<String, String>,
-    buffer: Vec<u8>,
+    dark_mode: DarkMode,
+    opacity_strategy: OpacityStrategy,
+    base: Vec<CssRule>,
+    /// The styles.toml and the files it extends, when compiled from source.
+    config_files: Vec<PathBuf>,
+    buffer: Cow<'static, [u8]>,
+}
+
+enum DarkMode {
+    Media,
+    Selector(String),
+}
+
+/// The group a rule is emitted in. Element styles come first, then components
+/// (shortcuts), then utilities, so that each group can override the previous.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    Base,
+    Components,
+    Utilities,
+}
+
+pub struct CssRule {
+    pub layer: Layer,
+    pub selector: String,
+    pub declarations: Vec<String>,
+    pub breakpoint: Option<usize>,
+    /// The number of variants other than breakpoints (e.g. 2 for md:dark:hover:p-4).
+    pub variant_depth: usize,
+    /// The position in styles.toml of the entry that generated the rule.
+    pub order: u32,
+    /// At-rules wrapping the rule, innermost first.
+    pub at_rules: Vec<String>,
+    /// Theme variables referenced by the declarations.
+    pub variables: Vec<String>,
+    /// Keyframes referenced by an animation declaration.
+    pub keyframes: Vec<String>,
+}
+
+/// The entry of styles.toml a class is generated from.
+pub enum Entry {
+    Static(String),
+    /// A [dynamic] key, matched by one of its suffixes or an arbitrary value.
+    Dynamic(String),
+    /// A [generators] key.
+    Generator(String),
+    Shortcut(String),
+    /// A class written as "[property:value]".
+    ArbitraryProperty,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::Static(name) => write!(f, "[static] {}", name),
+            Entry::Dynamic(key) => write!(f, "[dynamic] \"{}\"", key),
+            Entry::Generator(key) => write!(f, "[generators] \"{}\"", key),
+            Entry::Shortcut(name) => write!(f, "[shortcuts] {}", name),
+            Entry::ArbitraryProperty => write!(f, "an arbitrary property"),
+        }
+    }
+}
+
+/// A @keyframes block and the theme variables its steps reference.
+pub struct Keyframes {
+    name: String,
+    steps: Vec<(String, Vec<String>)>,
+    pub variables: Vec<String>,
+}
+
+impl Keyframes {
+    pub fn render(&self) -> String {
+        let steps: Vec<String> = self
+            .steps
+            .iter()
+            .map(|(step, declarations)| {
+                let declarations: Vec<String> = declarations
+                    .iter()
+                    .map(|declaration| format!("        {};", declaration))
+                    .collect();
+                format!("    {} {{\n{}\n    }}", step, declarations.join("\n"))
+            })
+            .collect();
+        format!("@keyframes {} {{\n{}\n}}", self.name, steps.join("\n"))
+    }
+}
+
+impl CssRule {
+    /// The key rules are emitted in, so that a rule can only be overridden by
+    /// rules sorting after it: components before utilities, then smaller
+    /// breakpoints (each emitted as one @media block), fewer variants, fewer
+    /// properties (p-4 before px-2) and earlier entries in styles.toml.
+    pub fn sort_key(&self) -> (Layer, Option<usize>, usize, usize, u32) {
+        (self.layer, self.breakpoint, self.variant_depth, self.declarations.len(), self.order)
+    }
+
+    pub fn render(&self) -> String {
+        let declarations: Vec<String> = self
+            .declarations
+            .iter()
+            .map(|declaration| format!("    {};", declaration))
+            .collect();
+        let mut rendered = format!("{} {{\n{}\n}}", self.selector, declarations.join("\n"));
+        for at_rule in &self.at_rules {
+            let body: Vec<String> = rendered.lines().map(|line| format!("    {}", line)).collect();
+            rendered = format!("{} {{\n{}\n}}", at_rule, body.join("\n"));
+        }
+        rendered
+    }
+
+    fn applies_to_same_target(&self, other: &CssRule) -> bool {
+        self.selector == other.selector && self.breakpoint == other.breakpoint && self.at_rules == other.at_rules
+    }
+
+    /// Adds the declarations of another rule, replacing any earlier
+    /// declaration of the same property.
+    fn merge(&mut self, other: CssRule) {
+        for declaration in other.declarations {
+            let property = declaration_property(&declaration);
+            self.declarations.retain(|existing| declaration_property(existing) != property);
+            self.declarations.push(declaration);
+        }
+        for variable in other.variables {
+            if !self.variables.contains(&variable) {
+                self.variables.push(variable);
+            }
+        }
+        for keyframes in other.keyframes {
+            if !self.keyframes.contains(&keyframes) {
+                self.keyframes.push(keyframes);
+            }
+        }
+        self.order = self.order.min(other.order);
+    }
 }
 
 impl StyleEngine {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let buffer = fs::read("styles.bin")?;
-        let config = unsafe { flatbuffers::root_unchecked::<style_schema::Config>(&buffer) };
+    /// The config embedded in the executable.
+    pub fn embedded() -> Result<Self, LoadError> {
+        Self::from_buffer(Cow::Borrowed(EMBEDDED_CONFIG))
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, LoadError> {
+        Self::from_buffer(Cow::Owned(fs::read(path).map_err(LoadError::Missing)?))
+    }
+
+    /// Loads the config found for the project: a styles.toml is compiled,
+    /// falling back to the styles.bin next to it if it doesn't compile, and a
+    /// styles.bin is loaded as is, unless it is corrupt or of another schema
+    /// version and a styles.toml next to it can be compiled instead. Without
+    /// one, the embedded config is used.
+    pub fn load(config_path: Option<&Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let Some(config_path) = config_path else {
+            return Ok(Self::embedded()?);
+        };
+        if !utils::is_toml_file(config_path) {
+            return match Self::from_file(config_path) {
+                Err(error @ (LoadError::Corrupt(_) | LoadError::VersionMismatch { .. })) => {
+                    let toml_path = config_path.with_file_name("styles.toml");
+                    if !toml_path.is_file() {
+                        return Err(error.into());
+                    }
+                    println!(
+                        "{} Recompiling {} from {}: {}",
+                        "Warning:".yellow(),
+                        config_path.display(),
+                        toml_path.display(),
+                        error
+                    );
+                    Self::from_toml(&toml_path)
+                }
+                loaded => Ok(loaded?),
+            };
+        }
+        Self::from_toml(config_path).or_else(|error| {
+            let binary_path = binary_path(config_path);
+            println!(
+                "{} Failed to compile {}, using the existing {}:\n{}",
+                "Warning:".yellow(),
+                config_path.display(),
+                binary_path.display(),
+                error
+            );
+            Ok(Self::from_file(&binary_path)?)
+        })
+    }
+
+    /// Compiles a styles.toml, saves the result to the styles.bin next to it
+    /// and loads it. A styles.bin that can't be written only gives a warning.
+    pub fn from_toml(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let engine = Self::from_config(&resolver::resolve(path)?)?;
+        let binary_path = binary_path(path);
+        if let Err(error) = fs::write(&binary_path, &engine.buffer) {
+            println!("{} Failed to write {}: {}", "Warning:".yellow(), binary_path.display(), error);
+        }
+        Ok(engine)
+    }
+
+    /// Compiles a resolved config in memory, without writing a styles.bin.
+    pub fn from_config(config: &resolver::ResolvedConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut engine = Self::from_buffer(Cow::Owned(compiler::compile(config)?))?;
+        engine.config_files = config.files().map(Path::to_path_buf).collect();
+        Ok(engine)
+    }
+
+    pub fn config_files(&self) -> &[PathBuf] {
+        &self.config_files
+    }
+
+    /// The hash of the sources the config was compiled from.
+    pub fn source_hash(&self) -> u64 {
+        self.config().source_hash()
+    }
+
+    /// The root table of the loaded styles.bin.
+    pub fn config(&self) -> style_schema::Config<'_> {
+        // The buffer was verified by from_buffer.
+        unsafe { flatbuffers::root_unchecked::<style_schema::Config>(&self.buffer) }
+    }
+
+    /// Finds a [static] class by binary search in styles.bin, which stores
+    /// the styles sorted by name. The other keyed tables are found the same way.
+    fn static_style(&self, name: &str) -> Option<style_schema::Style<'_>> {
+        self.config().styles()?.lookup_by_key(name, |style, name| style.key_compare_with_value(name))
+    }
+
+    fn variant(&self, name: &str) -> Option<style_schema::Variant<'_>> {
+        self.config().variants()?.lookup_by_key(name, |variant, name| variant.key_compare_with_value(name))
+    }
+
+    fn shortcut(&self, name: &str) -> Option<style_schema::Shortcut<'_>> {
+        self.config().shortcuts()?.lookup_by_key(name, |shortcut, name| shortcut.key_compare_with_value(name))
+    }
+
+    fn color(&self, palette: &str, name: &str) -> Option<style_schema::Color<'_>> {
+        let palette = self.config().palettes()?.lookup_by_key(palette, |palette, name| palette.key_compare_with_value(name))?;
+        palette.colors()?.lookup_by_key(name, |color, name| color.key_compare_with_value(name))
+    }
+
+    /// The index of a breakpoint, in ascending min-width order.
+    fn breakpoint(&self, name: &str) -> Option<usize> {
+        self.config().breakpoints()?.iter().position(|breakpoint| breakpoint.name() == Some(name))
+    }
 
-        let mut precompiled = HashMap::new();
-        if let Some(styles) = config.styles() {
-            for style in styles {
-                let name = style.name();
-                if let Some(css) = style.css() {
-                    precompiled.insert(name.to_string(), css.to_string());
+    /// Verifies a styles.bin buffer before reading anything from it.
+    fn from_buffer(buffer: Cow<'static, [u8]>) -> Result<Self, LoadError> {
+        if !style_schema::config_buffer_has_identifier(&buffer) {
+            return Err(LoadError::Corrupt(format!(
+                "missing the '{}' file identifier",
+                style_schema::CONFIG_IDENTIFIER
+            )));
+        }
+        let config = style_schema::root_as_config(&buffer).map_err(|error| LoadError::Corrupt(error.to_string().trim_end().to_string()))?;
+        if config.schema_version() != compiler::SCHEMA_VERSION {
+            return Err(LoadError::VersionMismatch {
+                found: config.schema_version(),
+                expected: compiler::SCHEMA_VERSION,
+            });
+        }
+
+        let dark_mode = match config.dark_mode() {
+            Some(dark_mode) if dark_mode.strategy() != style_schema::DarkModeStrategy::Media => {
+                DarkMode::Selector(dark_mode.selector().unwrap_or(".dark").to_string())
+            }
+            _ => DarkMode::Media,
+        };
+
+        let opacity_strategy = match config.opacity_strategy() {
+            style_schema::OpacityStrategy::Rgba => OpacityStrategy::Rgba,
+            _ => OpacityStrategy::ColorMix,
+        };
+
+        let mut base = Vec::new();
+        if let Some(base_rules) = config.base() {
+            for (order, base_rule) in base_rules.iter().enumerate() {
+                if let (Some(selector), Some(css)) = (base_rule.selector(), base_rule.css()) {
+                    let declarations = split_declarations(css);
+                    let variables = referenced_variables(config, &declarations);
+                    base.push(CssRule {
+                        layer: Layer::Base,
+                        selector: selector.to_string(),
+                        declarations,
+                        breakpoint: None,
+                        variant_depth: 0,
+                        order: order as u32,
+                        at_rules: Vec::new(),
+                        variables,
+                        keyframes: Vec::new(),
+                    });
                 }
             }
         }
 
-        Ok(Self {
-            precompiled,
-            buffer,
-        })
+        Ok(Self { dark_mode, opacity_strategy, base, config_files: Vec::new(), buffer })
     }
 
-    pub fn generate_css_for_class(&self, class_name: &str) -> Option<String> {
-        if let Some(css) = self.precompiled.get(class_name) {
-            return Some(format!(".{} {{\n    {}\n}}", class_name, css));
-        }
-
-        let config = unsafe { flatbuffers::root_unchecked::<style_schema::Config>(&self.buffer) };
-        if let Some(generators) = config.generators() {
-            for generator in generators {
-                if let (Some(prefix), Some(property), Some(unit)) = (
-                    generator.prefix(),
-                    generator.property(),
-                    generator.unit(),
-                ) {
-                    if class_name.starts_with(&format!("{}-", prefix)) {
-                        let value_str = &class_name[prefix.len() + 1..];
-                        if let Ok(num_val) = value_str.parse::<f32>() {
-                            let final_value = num_val * generator.multiplier();
-                            let css = format!("{}: {}{};", property, final_value, unit);
-                            return Some(format!(".{} {{\n    {}\n}}", class_name, css));
-                        }
-                    }
+    pub fn breakpoint_media_query(&self, breakpoint: usize) -> String {
+        let min_width = element(self.config().breakpoints(), breakpoint).and_then(|breakpoint| breakpoint.min_width());
+        format!("@media (min-width: {})", min_width.unwrap_or_default())
+    }
+
+    /// The rules of the base layer: the preflight reset and the [base] element
+    /// styles, in order.
+    pub fn base_rules(&self) -> &[CssRule] {
+        &self.base
+    }
+
+    /// Reads a @keyframes block and the theme variables its steps reference.
+    pub fn keyframes(&self, name: &str) -> Option<Keyframes> {
+        let config = self.config();
+        let keyframes = config.keyframes()?.lookup_by_key(name, |keyframes, name| keyframes.key_compare_with_value(name))?;
+        let mut steps = Vec::new();
+        let mut variables = Vec::new();
+        for step in keyframes.steps().into_iter().flatten() {
+            if let (Some(selector), Some(css)) = (step.selector(), step.css()) {
+                let declarations = split_declarations(css);
+                variables.extend(referenced_variables(config, &declarations));
+                steps.push((selector.to_string(), declarations));
+            }
+        }
+        Some(Keyframes { name: name.to_string(), steps, variables })
+    }
+
+    /// Renders the :root block declaring the theme variables referenced by the
+    /// given ones (or every variable when configured to emit all of them).
+    pub fn render_theme_variables(&self, used: &HashSet<String>) -> Option<String> {
+        let config = self.config();
+        let mut variables: Vec<style_schema::ThemeVariable<'_>> = if config.emit_all_variables() {
+            config.variables().into_iter().flatten().collect()
+        } else {
+            let mut pending: Vec<&str> = used.iter().map(String::as_str).collect();
+            let mut names = HashSet::new();
+            let mut variables = Vec::new();
+            while let Some(name) = pending.pop() {
+                if let Some(variable) = theme_variable(config, name)
+                    && names.insert(variable.name())
+                {
+                    pending.extend(variable_references(variable.value().unwrap_or_default()));
+                    variables.push(variable);
+                }
+            }
+            variables.sort_by_key(|variable| variable.name());
+            variables
+        };
+        variables.retain(|variable| variable.value().is_some());
+        if variables.is_empty() {
+            return None;
+        }
+
+        let declarations: Vec<String> = variables
+            .into_iter()
+            .map(|variable| format!("    {}: {};", variable.name(), variable.value().unwrap_or_default()))
+            .collect();
+        Some(format!(":root {{\n{}\n}}", declarations.join("\n")))
+    }
+
+    /// Generates the rules for a class: a single rule for a utility, or one
+    /// merged rule per variant combination for a shortcut.
+    pub fn generate_css_for_class(&self, class_name: &str) -> Vec<CssRule> {
+        let mut parts = split_variants(class_name);
+        let Some(utility) = parts.pop() else {
+            return Vec::new();
+        };
+        let selector = format!(".{}", escape_class_name(class_name));
+
+        if let Some(classes) = self.shortcut(utility).and_then(|shortcut| shortcut.classes()) {
+            return self.expand_shortcut(&selector, &parts, classes);
+        }
+        self.build_rule(&selector, &parts, utility, Layer::Utilities).into_iter().collect()
+    }
+
+    /// Finds the entry a class is generated from, if it generates anything.
+    pub fn matching_entry(&self, class_name: &str) -> Option<Entry> {
+        if self.generate_css_for_class(class_name).is_empty() {
+            return None;
+        }
+        let utility = split_variants(class_name).pop()?;
+        if self.shortcut(utility).is_some() {
+            return Some(Entry::Shortcut(utility.to_string()));
+        }
+        let utility = utility.strip_prefix('!').unwrap_or(utility);
+        let (_, order) = self.resolve_utility(utility)?;
+        if order == u32::MAX {
+            return Some(Entry::ArbitraryProperty);
+        }
+        // Every entry has its own order, shared by the suffixes of a [dynamic] key.
+        let config = self.config();
+        let key = |prefix: Option<&str>, properties: Option<flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<&str>>>| {
+            let properties: Vec<&str> = properties.into_iter().flatten().collect();
+            format!("{}|{}", prefix.unwrap_or_default(), properties.join(","))
+        };
+        if let Some(dynamic) = config.dynamics().into_iter().flatten().find(|dynamic| dynamic.order() == order) {
+            return Some(Entry::Dynamic(key(dynamic.prefix(), dynamic.properties())));
+        }
+        if let Some(generator) = config.generators().into_iter().flatten().find(|generator| generator.order() == order) {
+            return Some(Entry::Generator(key(generator.prefix(), generator.properties())));
+        }
+        Some(Entry::Static(utility.to_string()))
+    }
+
+    /// Builds the rules of a shortcut under its own selector. The variants the
+    /// shortcut is used with (e.g. md:btn) wrap those of its classes. Classes
+    /// that match no entry are skipped (see unresolved_shortcut_classes).
+    fn expand_shortcut(
+        &self,
+        selector: &str,
+        outer_variants: &[&str],
+        classes: flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<&str>>,
+    ) -> Vec<CssRule> {
+        let mut rules: Vec<CssRule> = Vec::new();
+        for class in classes {
+            let mut parts = split_variants(class);
+            let Some(utility) = parts.pop() else { continue };
+            let variants: Vec<&str> = outer_variants.iter().chain(parts.iter()).copied().collect();
+            let Some(rule) = self.build_rule(selector, &variants, utility, Layer::Components) else {
+                continue;
+            };
+            match rules.iter_mut().find(|existing| existing.applies_to_same_target(&rule)) {
+                Some(existing) => existing.merge(rule),
+                None => rules.push(rule),
+            }
+        }
+        rules
+    }
+
+    /// The classes of each shortcut that match no entry (or use an unknown
+    /// variant), as (shortcut, class) pairs in shortcut order.
+    pub fn unresolved_shortcut_classes(&self) -> Vec<(&str, &str)> {
+        let mut unresolved = Vec::new();
+        for shortcut in self.config().shortcuts().into_iter().flatten() {
+            for class in shortcut.classes().into_iter().flatten() {
+                let mut parts = split_variants(class);
+                let resolved = parts
+                    .pop()
+                    .is_some_and(|utility| self.build_rule("", &parts, utility, Layer::Components).is_some());
+                if !resolved {
+                    unresolved.push((shortcut.name(), class));
+                }
+            }
+        }
+        unresolved
+    }
+
+    /// Warns about the shortcut classes that are skipped because they match
+    /// no entry.
+    pub fn warn_unresolved_shortcut_classes(&self) {
+        for (shortcut, class) in self.unresolved_shortcut_classes() {
+            println!(
+                "{} The shortcut '{}' uses '{}', which matches no entry, so it is left out",
+                "Warning:".yellow(),
+                shortcut,
+                class
+            );
+        }
+    }
+
+    fn build_rule(&self, selector: &str, variants: &[&str], utility: &str, layer: Layer) -> Option<CssRule> {
+        let (declarations, order) = match utility.strip_prefix('!') {
+            Some(utility) => {
+                let (declarations, order) = self.resolve_utility(utility)?;
+                (important(declarations), order)
+            }
+            None => self.resolve_utility(utility)?,
+        };
+
+        let mut selector = selector.to_string();
+        let mut breakpoint = None;
+        let mut variant_depth = 0;
+        let mut at_rules = Vec::new();
+        for variant in variants.iter().rev() {
+            if let Some(index) = self.breakpoint(variant) {
+                // Stacked min-width queries collapse to the widest one.
+                breakpoint = breakpoint.max(Some(index));
+                continue;
+            }
+            variant_depth += 1;
+            if *variant == "dark" {
+                match &self.dark_mode {
+                    DarkMode::Media => at_rules.push("@media (prefers-color-scheme: dark)".to_string()),
+                    DarkMode::Selector(dark) => selector = format!("{}:where({}, {} *)", selector, dark, dark),
+                }
+                continue;
+            }
+            let template = self.variant(variant)?.selector()?;
+            selector = template.replace('&', &selector);
+        }
+
+        let variables = referenced_variables(self.config(), &declarations);
+        let keyframes = self.referenced_keyframes(&declarations);
+
+        Some(CssRule { layer, selector, declarations, breakpoint, variant_depth, order, at_rules, variables, keyframes })
+    }
+
+    /// The keyframes named in animation or animation-name declarations, e.g.
+    /// "spin" in "animation: spin 1s linear infinite".
+    fn referenced_keyframes(&self, declarations: &[String]) -> Vec<String> {
+        let mut keyframes = Vec::new();
+        for declaration in declarations {
+            let Some((property, value)) = declaration.split_once(':') else {
+                continue;
+            };
+            if !matches!(property.trim(), "animation" | "animation-name") {
+                continue;
+            }
+            for word in value.split(|ch: char| ch == ',' || ch.is_whitespace()) {
+                let is_keyframes = self.config().keyframes().and_then(|all| {
+                    all.lookup_by_key(word, |keyframes, name| keyframes.key_compare_with_value(name))
+                });
+                if is_keyframes.is_some() && !keyframes.iter().any(|name| name == word) {
+                    keyframes.push(word.to_string());
+                }
+            }
+        }
+        keyframes
+    }
+
+    /// Returns the declarations of a utility and the order of the entry that
+    /// generated them. Arbitrary properties sort after every entry.
+    fn resolve_utility(&self, utility: &str) -> Option<(Vec<String>, u32)> {
+        if let Some(style) = self.static_style(utility)
+            && let Some(css) = style.css()
+        {
+            return Some((split_declarations(css), style.order()));
+        }
+        if utility.starts_with('[') {
+            return arbitrary_property(utility).map(|declarations| (declarations, u32::MAX));
+        }
+        match utility.strip_prefix('-') {
+            Some(utility) => self.resolve_prefixed(utility, true),
+            None => self.resolve_prefixed(utility, false),
+        }
+    }
+
+    /// Resolves a class with the generator and dynamic entries whose prefix
+    /// it starts with, found in the prefix trie. Longer prefixes are tried
+    /// first, so "border-t-4" is never read as "border-" with "t-4".
+    fn resolve_prefixed(&self, utility: &str, negative: bool) -> Option<(Vec<String>, u32)> {
+        let trie = self.config().trie()?;
+        self.resolve_from_node(trie, 0, utility, 0, negative)
+    }
+
+    fn resolve_from_node(
+        &self,
+        trie: flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<style_schema::TrieNode<'_>>>,
+        node: usize,
+        utility: &str,
+        depth: usize,
+        negative: bool,
+    ) -> Option<(Vec<String>, u32)> {
+        let node = element(Some(trie), node)?;
+        if let (Some(byte), Some(bytes)) = (utility.as_bytes().get(depth), node.bytes())
+            && let Ok(edge) = bytes.bytes().binary_search(byte)
+            && let Some(child) = element(node.children(), edge)
+            && let Some(resolved) = self.resolve_from_node(trie, child as usize, utility, depth + 1, negative)
+        {
+            return Some(resolved);
+        }
+
+        // Every prefix ends with '-', so a node with matches is at a char boundary.
+        let value = utility.get(depth..)?;
+        let config = self.config();
+        for entry in node.matches()? {
+            let index = entry.index() as usize;
+            let resolved = match entry.kind() {
+                style_schema::MatchKind::Color if !negative => {
+                    element(config.generators(), index).and_then(|generator| self.resolve_color(generator, value))
                 }
+                style_schema::MatchKind::Dynamic if !negative => {
+                    element(config.dynamics(), index).and_then(|dynamic| resolve_dynamic(dynamic, value))
+                }
+                style_schema::MatchKind::Generator => element(config.generators(), index)
+                    .and_then(|generator| self.resolve_generator(generator, value, negative)),
+                _ => None,
+            };
+            if resolved.is_some() {
+                return resolved;
             }
         }
         None
     }
+
+    /// Resolves the value of a color generator class such as "bg-red-500" or
+    /// "text-[#1da1f2]". Only values that are colors match, so "text-xl" and
+    /// "text-[14px]" are left to the other entries sharing the prefix.
+    fn resolve_color(&self, generator: style_schema::Generator<'_>, value_str: &str) -> Option<(Vec<String>, u32)> {
+        let (Some(properties), Some(palette)) = (generator.properties(), generator.palette()) else {
+            return None;
+        };
+        let (value_str, alpha) = match split_opacity_modifier(value_str) {
+            Some((value_str, modifier)) => (value_str, Some(color::parse_alpha(modifier)?)),
+            None => (value_str, None),
+        };
+
+        let (color, literal) = if let Some(value) = arbitrary_value(value_str) {
+            let color = match value.strip_prefix("color:") {
+                Some(hinted) if hinted.trim().is_empty() => return None,
+                Some(hinted) => hinted.to_string(),
+                None if is_color_value(&value) => value,
+                None => return None,
+            };
+            (color.clone(), color)
+        } else {
+            let color = self.color(palette, value_str)?;
+            let value = color.value()?;
+            (value.to_string(), color.literal().unwrap_or(value).to_string())
+        };
+        let color = match alpha {
+            Some(alpha) => color::with_opacity(&color, &literal, alpha, self.opacity_strategy),
+            None => color,
+        };
+        Some((declarations(properties, &color), generator.order()))
+    }
+
+    fn resolve_generator(
+        &self,
+        generator: style_schema::Generator<'_>,
+        value_str: &str,
+        negative: bool,
+    ) -> Option<(Vec<String>, u32)> {
+        if negative && !generator.negative() {
+            return None;
+        }
+        let (Some(properties), Some(unit)) = (generator.properties(), generator.unit()) else {
+            return None;
+        };
+        let sign = if negative { -1.0 } else { 1.0 };
+
+        let value = if let Some(value) = unhinted_arbitrary_value(value_str) {
+            if negative { format!("calc({} * -1)", value) } else { value }
+        } else if let Some(template) = generator.template() {
+            template.replace("{n}", &template_input(value_str, &generator, sign)?)
+        } else if let Some((numerator, denominator)) = value_str.split_once('/') {
+            if !generator.fraction() {
+                return None;
+            }
+            let (Some(numerator), Some(denominator)) = (parse_plain_number(numerator), parse_plain_number(denominator)) else {
+                return None;
+            };
+            if !numerator.is_finite() || !denominator.is_finite() || denominator == 0.0 {
+                return None;
+            }
+            format!("{}%", format_number(sign * numerator / denominator * 100.0))
+        } else if let Some(num_val) = parse_plain_number(value_str).map(|number| number as f32)
+            && num_val.is_finite()
+        {
+            match generator.variable() {
+                Some(variable) => format!("calc(var({}) * {}{})", variable, sign as f32 * num_val + 0.0, unit),
+                None => {
+                    let final_value = sign as f32 * num_val * generator.multiplier() + 0.0;
+                    format!("{}{}", final_value, unit)
+                }
+            }
+        } else {
+            return None;
+        };
+        Some((declarations(properties, &value), generator.order()))
+    }
+}
+
+/// Parses the input of a template generator according to its type, checks it
+/// against the generator's min, max and step, and returns what replaces "{n}".
+fn template_input(value: &str, generator: &style_schema::Generator, sign: f64) -> Option<String> {
+    let (number, input) = match generator.input() {
+        style_schema::InputType::Integer => {
+            if value.is_empty() || !value.chars().all(|ch| ch.is_ascii_digit()) {
+                return None;
+            }
+            let number = sign * value.parse::<i64>().ok()? as f64;
+            (number, format!("{}", number as i64))
+        }
+        style_schema::InputType::Percentage => {
+            let number = sign * parse_plain_number(value)?;
+            (number, format_number(number / 100.0))
+        }
+        style_schema::InputType::Fraction => {
+            let (numerator, denominator) = value.split_once('/')?;
+            let denominator = parse_plain_number(denominator)?;
+            if denominator == 0.0 {
+                return None;
+            }
+            let number = sign * parse_plain_number(numerator)? / denominator * 100.0;
+            (number, format!("{}%", format_number(number)))
+        }
+        _ => {
+            let number = sign * parse_plain_number(value)?;
+            (number, format_number(number))
+        }
+    };
+
+    if generator.min().is_some_and(|min| number < min as f64) || generator.max().is_some_and(|max| number > max as f64) {
+        return None;
+    }
+    let step = generator.step() as f64;
+    if step > 0.0 {
+        let steps = (number - generator.min().unwrap_or(0.0) as f64) / step;
+        if (steps - steps.round()).abs() > 1e-6 {
+            return None;
+        }
+    }
+    Some(input)
+}
+
+/// Parses an unsigned decimal number written with digits only (no exponent,
+/// sign, "inf" or "NaN").
+fn parse_plain_number(value: &str) -> Option<f64> {
+    let (integer, fraction) = value.split_once('.').unwrap_or((value, "0"));
+    let is_digits = |part: &str| !part.is_empty() && part.chars().all(|ch| ch.is_ascii_digit());
+    if !is_digits(integer) || !is_digits(fraction) {
+        return None;
+    }
+    value.parse::<f64>().ok()
+}
+
+/// Formats a number with at most six decimals and no negative zero.
+fn format_number(value: f64) -> String {
+    format!("{}", (value * 1e6).round() / 1e6 + 0.0)
+}
+
+/// Builds one declaration per property, all with the same value.
+fn declarations(properties: flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<&str>>, value: &str) -> Vec<String> {
+    properties.iter().map(|property| format!("{}: {}", property, value)).collect()
+}
+
+/// Where the styles.bin compiled from a styles.toml is written.
+pub fn binary_path(config_path: &Path) -> PathBuf {
+    config_path.with_file_name("styles.bin")
+}
+
+/// The element at an index read from styles.bin, which verification doesn't
+/// bounds-check.
+fn element<'a, T: flatbuffers::Follow<'a> + 'a>(vector: Option<flatbuffers::Vector<'a, T>>, index: usize) -> Option<T::Inner> {
+    let vector = vector?;
+    (index < vector.len()).then(|| vector.get(index))
+}
+
+/// Resolves an arbitrary value of a [dynamic] prefix, such as "rounded-[3px]".
+fn resolve_dynamic(dynamic: style_schema::Dynamic<'_>, value_str: &str) -> Option<(Vec<String>, u32)> {
+    let value = unhinted_arbitrary_value(value_str)?;
+    Some((declarations(dynamic.properties()?, &value), dynamic.order()))
+}
+
+/// Finds a theme variable by binary search in styles.bin.
+fn theme_variable<'a>(config: style_schema::Config<'a>, name: &str) -> Option<style_schema::ThemeVariable<'a>> {
+    config.variables()?.lookup_by_key(name, |variable, name| variable.key_compare_with_value(name))
+}
+
+/// The theme variables referenced by some declarations.
+fn referenced_variables(config: style_schema::Config<'_>, declarations: &[String]) -> Vec<String> {
+    declarations
+        .iter()
+        .flat_map(|declaration| variable_references(declaration))
+        .filter(|name| theme_variable(config, name).is_some())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Splits a block of CSS declarations on the ';' separators that are not
+/// inside parentheses or quotes (e.g. in a data: URL).
+pub fn split_declarations(css: &str) -> Vec<String> {
+    let mut declarations = Vec::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut start = 0;
+    for (index, ch) in css.char_indices() {
+        match (ch, quote) {
+            ('"' | '\'', None) => quote = Some(ch),
+            (_, Some(open)) if ch == open => quote = None,
+            (_, Some(_)) => {}
+            ('(', None) => depth += 1,
+            (')', None) => depth = depth.saturating_sub(1),
+            (';', None) if depth == 0 => {
+                declarations.push(css[start..index].trim().to_string());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    declarations.push(css[start..].trim().to_string());
+    declarations.retain(|declaration| !declaration.is_empty());
+    declarations
+}
+
+/// Returns the names of the custom properties used through var() in a value.
+fn variable_references(css: &str) -> Vec<&str> {
+    let mut references = Vec::new();
+    let mut rest = css;
+    while let Some(start) = rest.find("var(--") {
+        let name_start = &rest[start + 4..];
+        let end = name_start[2..]
+            .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '-' || ch == '_'))
+            .map_or(name_start.len(), |end| end + 2);
+        references.push(&name_start[..end]);
+        rest = &name_start[end..];
+    }
+    references
+}
+
+fn declaration_property(declaration: &str) -> &str {
+    declaration.split_once(':').map_or(declaration, |(property, _)| property.trim())
+}
+
+/// Marks every declaration as !important.
+fn important(declarations: Vec<String>) -> Vec<String> {
+    declarations
+        .into_iter()
+        .map(|declaration| format!("{} !important", declaration))
+        .collect()
+}
+
+/// Splits a class name on the ':' variant separators, ignoring any ':' inside
+/// square brackets (e.g. "hover:bg-[url(https://x)]").
+fn split_variants(class_name: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (index, ch) in class_name.char_indices() {
+        match ch {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            ':' if depth == 0 => {
+                parts.push(&class_name[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&class_name[start..]);
+    parts
+}
+
+/// Splits a color value from its opacity modifier at the last '/' outside of
+/// square brackets, e.g. "red-500/50" or "[#1da1f2]/[0.37]".
+fn split_opacity_modifier(value: &str) -> Option<(&str, &str)> {
+    let mut depth = 0usize;
+    let mut split = None;
+    for (index, ch) in value.char_indices() {
+        match ch {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            '/' if depth == 0 => split = Some(index),
+            _ => {}
+        }
+    }
+    split.map(|index| (&value[..index], &value[index + 1..]))
+}
+
+/// Returns the CSS value of a bracketed arbitrary value such as "[200px_1fr]",
+/// where "_" stands for a space and "\_" for a literal underscore. Values that
+/// could end the declaration or the rule are rejected: ';', braces, comments
+/// and unbalanced quotes, brackets or parentheses.
+fn arbitrary_value(value: &str) -> Option<String> {
+    let inner = value.strip_prefix('[')?.strip_suffix(']')?;
+    if inner.is_empty() || inner.contains([';', '{', '}']) || inner.contains("/*") || inner.contains("*/") {
+        return None;
+    }
+    if !is_balanced(inner) {
+        return None;
+    }
+
+    let mut resolved = String::with_capacity(inner.len());
+    let mut chars = inner.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' if chars.peek() == Some(&'_') => {
+                resolved.push('_');
+                chars.next();
+            }
+            '_' => resolved.push(' '),
+            _ => resolved.push(ch),
+        }
+    }
+    Some(resolved)
+}
+
+/// An arbitrary value for entries other than color generators, which are the
+/// only ones reading a "color:" hint.
+fn unhinted_arbitrary_value(value: &str) -> Option<String> {
+    arbitrary_value(value).filter(|value| !value.starts_with("color:"))
+}
+
+/// Whether every quote, bracket and parenthesis of a value is closed, in
+/// order, and no '\\' escapes the end of the value.
+fn is_balanced(value: &str) -> bool {
+    let mut closers = Vec::new();
+    let mut quote = None;
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            if chars.next().is_none() {
+                return false;
+            }
+            continue;
+        }
+        match (ch, quote) {
+            (_, Some(open)) if ch == open => quote = None,
+            (_, Some(_)) => {}
+            ('"' | '\'', None) => quote = Some(ch),
+            ('(', None) => closers.push(')'),
+            ('[', None) => closers.push(']'),
+            (')' | ']', None) if closers.pop() != Some(ch) => return false,
+            _ => {}
+        }
+    }
+    quote.is_none() && closers.is_empty()
+}
+
+/// The named colors of CSS Color Module Level 4, sorted.
+const NAMED_COLORS: [&str; 148] = [
+    "aliceblue", "antiquewhite", "aqua", "aquamarine", "azure", "beige", "bisque", "black", "blanchedalmond",
+    "blue", "blueviolet", "brown", "burlywood", "cadetblue", "chartreuse", "chocolate", "coral",
+    "cornflowerblue", "cornsilk", "crimson", "cyan", "darkblue", "darkcyan", "darkgoldenrod", "darkgray",
+    "darkgreen", "darkgrey", "darkkhaki", "darkmagenta", "darkolivegreen", "darkorange", "darkorchid",
+    "darkred", "darksalmon", "darkseagreen", "darkslateblue", "darkslategray", "darkslategrey",
+    "darkturquoise", "darkviolet", "deeppink", "deepskyblue", "dimgray", "dimgrey", "dodgerblue", "firebrick",
+    "floralwhite", "forestgreen", "fuchsia", "gainsboro", "ghostwhite", "gold", "goldenrod", "gray", "green",
+    "greenyellow", "grey", "honeydew", "hotpink", "indianred", "indigo", "ivory", "khaki", "lavender",
+    "lavenderblush", "lawngreen", "lemonchiffon", "lightblue", "lightcoral", "lightcyan",
+    "lightgoldenrodyellow", "lightgray", "lightgreen", "lightgrey", "lightpink", "lightsalmon",
+    "lightseagreen", "lightskyblue", "lightslategray", "lightslategrey", "lightsteelblue", "lightyellow",
+    "lime", "limegreen", "linen", "magenta", "maroon", "mediumaquamarine", "mediumblue", "mediumorchid",
+    "mediumpurple", "mediumseagreen", "mediumslateblue", "mediumspringgreen", "mediumturquoise",
+    "mediumvioletred", "midnightblue", "mintcream", "mistyrose", "moccasin", "navajowhite", "navy", "oldlace",
+    "olive", "olivedrab", "orange", "orangered", "orchid", "palegoldenrod", "palegreen", "paleturquoise",
+    "palevioletred", "papayawhip", "peachpuff", "peru", "pink", "plum", "powderblue", "purple",
+    "rebeccapurple", "red", "rosybrown", "royalblue", "saddlebrown", "salmon", "sandybrown", "seagreen",
+    "seashell", "sienna", "silver", "skyblue", "slateblue", "slategray", "slategrey", "snow", "springgreen",
+    "steelblue", "tan", "teal", "thistle", "tomato", "turquoise", "violet", "wheat", "white", "whitesmoke",
+    "yellow", "yellowgreen",
+];
+
+/// Returns true for values that are unambiguously colors: hex colors, color
+/// functions, transparent, currentColor and the named colors.
+fn is_color_value(value: &str) -> bool {
+    if let Some(hex) = value.strip_prefix('#') {
+        return matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|ch| ch.is_ascii_hexdigit());
+    }
+    const COLOR_FUNCTIONS: [&str; 11] = [
+        "rgb(", "rgba(", "hsl(", "hsla(", "hwb(", "lab(", "lch(", "oklab(", "oklch(", "color(", "color-mix(",
+    ];
+    let lower = value.to_ascii_lowercase();
+    COLOR_FUNCTIONS.iter().any(|function| lower.starts_with(function))
+        || lower == "transparent"
+        || lower == "currentcolor"
+        || NAMED_COLORS.binary_search(&lower.as_str()).is_ok()
+}
+
+/// Resolves an arbitrary property utility such as "[mask-type:luminance]" or
+/// "[--sidebar-width:240px]" into its single declaration.
+fn arbitrary_property(utility: &str) -> Option<Vec<String>> {
+    let inner = utility.strip_prefix('[')?.strip_suffix(']')?;
+    let (property, value) = inner.split_once(':')?;
+    if !validation::is_valid_property_name(property) {
+        return None;
+    }
+    let value = arbitrary_value(&format!("[{}]", value))?;
+    Some(vec![format!("{}: {}", property, value)])
+}
+
+/// Escapes a class name for use in a selector, following the CSS.escape()
+/// algorithm from the CSSOM specification.
+fn escape_class_name(class_name: &str) -> String {
+    let mut escaped = String::with_capacity(class_name.len() + 8);
+    let first = class_name.chars().next();
+    for (index, ch) in class_name.chars().enumerate() {
+        match ch {
+            '\0' => escaped.push('\u{FFFD}'),
+            '\u{1}'..='\u{1F}' | '\u{7F}' => escaped.push_str(&format!("\\{:x} ", ch as u32)),
+            '0'..='9' if index == 0 || (index == 1 && first == Some('-')) => {
+                escaped.push_str(&format!("\\{:x} ", ch as u32))
+            }
+            '-' if index == 0 && class_name.len() == 1 => escaped.push_str("\\-"),
+            '-' | '_' | 'a'..='z' | 'A'..='Z' | '0'..='9' => escaped.push(ch),
+            _ if !ch.is_ascii() => escaped.push(ch),
+            _ => {
+                escaped.push('\\');
+                escaped.push(ch);
+            }
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compiles a styles.toml held in memory and loads the result.
+    fn engine(toml: &str) -> StyleEngine {
+        let source = resolver::Source { name: "styles.toml".to_string(), path: None, text: toml.to_string() };
+        StyleEngine::from_config(&resolver::resolve_source(source).unwrap()).unwrap()
+    }
+
+    fn resolved(engine: &StyleEngine, utility: &str) -> Option<Vec<String>> {
+        engine.resolve_prefixed(utility, false).map(|(declarations, _)| declarations)
+    }
+
+    fn entry(engine: &StyleEngine, class_name: &str) -> Option<String> {
+        engine.matching_entry(class_name).map(|entry| entry.to_string())
+    }
+
+    const SHARED_PREFIXES: &str = r##"
+        [theme.colors]
+        red = { 500 = "#ef4444" }
+
+        [dynamic]
+        "text|font-size" = { xl = "1.25rem" }
+        "rounded|border-radius" = { sm = "0.125rem" }
+        "rounded-t|border-top-left-radius,border-top-right-radius" = { sm = "0.125rem" }
+
+        [generators]
+        "text|font-size" = { multiplier = 1, unit = "px" }
+        "text|color" = { palette = "colors" }
+        "m|margin" = { multiplier = 1, unit = "px", negative = true }
+        "mx|margin-left,margin-right" = { multiplier = 1, unit = "px" }
+    "##;
+
+    #[test]
+    fn escape_class_name_escapes_a_leading_digit() {
+        assert_eq!(escape_class_name("2xl:p-4"), "\\32 xl\\:p-4");
+        assert_eq!(escape_class_name("-1"), "-\\31 ");
+        assert_eq!(escape_class_name("-m-4"), "-m-4");
+        assert_eq!(escape_class_name("p-4"), "p-4");
+    }
+
+    #[test]
+    fn escape_class_name_handles_dashes_and_control_characters() {
+        assert_eq!(escape_class_name("-"), "\\-");
+        assert_eq!(escape_class_name("--x"), "--x");
+        assert_eq!(escape_class_name("a\u{1}b"), "a\\1 b");
+        assert_eq!(escape_class_name("a\u{7F}"), "a\\7f ");
+        assert_eq!(escape_class_name("a\0"), "a\u{FFFD}");
+    }
+
+    #[test]
+    fn escape_class_name_escapes_punctuation_but_not_unicode() {
+        assert_eq!(escape_class_name("w-1/2"), "w-1\\/2");
+        assert_eq!(escape_class_name("p-0.5"), "p-0\\.5");
+        assert_eq!(escape_class_name("bg-[#fff]"), "bg-\\[\\#fff\\]");
+        assert_eq!(escape_class_name("!p-4"), "\\!p-4");
+        assert_eq!(escape_class_name("é_x"), "é_x");
+    }
+
+    #[test]
+    fn arbitrary_value_turns_underscores_into_spaces() {
+        assert_eq!(arbitrary_value("[200px_1fr]").as_deref(), Some("200px 1fr"));
+        assert_eq!(arbitrary_value("[var(--my\\_var)]").as_deref(), Some("var(--my_var)"));
+        assert_eq!(arbitrary_value("[a\\b]").as_deref(), Some("a\\b"));
+        assert_eq!(arbitrary_value("[3px]").as_deref(), Some("3px"));
+    }
+
+    #[test]
+    fn arbitrary_value_rejects_empty_unbracketed_and_unsafe_values() {
+        let unsafe_values = [
+            "[]", "3px", "[3px", "3px]", "[a;b]", "[a{b]", "[a}b]", "[1px/*]", "[*/1px]", "[\"]", "['a]", "[url(a]",
+            "[a)]", "[calc(1px_+_(2px)]", "[a[b]", "[(a])]", "[a\\]",
+        ];
+        for value in unsafe_values {
+            assert_eq!(arbitrary_value(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn arbitrary_value_accepts_balanced_quotes_and_parentheses() {
+        assert_eq!(arbitrary_value("[url('a_b.png')]").as_deref(), Some("url('a b.png')"));
+        assert_eq!(arbitrary_value("[\"it's\"]").as_deref(), Some("\"it's\""));
+        assert_eq!(arbitrary_value("[calc(100%_-_(2*var(--x)))]").as_deref(), Some("calc(100% - (2*var(--x)))"));
+        assert_eq!(arbitrary_value("[1px/2px]").as_deref(), Some("1px/2px"));
+    }
+
+    #[test]
+    fn color_hints_need_a_value() {
+        let engine = engine(SHARED_PREFIXES);
+        assert_eq!(resolved(&engine, "text-[color:]"), None);
+        assert_eq!(resolved(&engine, "text-[color:var(--brand)]"), Some(vec!["color: var(--brand)".to_string()]));
+    }
+
+    #[test]
+    fn split_variants_ignores_colons_in_brackets() {
+        assert_eq!(split_variants("p-4"), vec!["p-4"]);
+        assert_eq!(split_variants("md:hover:p-4"), vec!["md", "hover", "p-4"]);
+        assert_eq!(split_variants("hover:bg-[url(https://x)]"), vec!["hover", "bg-[url(https://x)]"]);
+        assert_eq!(split_variants("[mask-type:luminance]"), vec!["[mask-type:luminance]"]);
+        assert_eq!(split_variants("md:"), vec!["md", ""]);
+    }
+
+    #[test]
+    fn generator_values_are_plain_numbers() {
+        let engine = engine(SHARED_PREFIXES);
+        assert_eq!(resolved(&engine, "m-1.5"), Some(vec!["margin: 1.5px".to_string()]));
+        for utility in ["text--4", "text-+4", "text-1e3", "text-inf", "text-NaN", "text-.5", "m--4"] {
+            assert_eq!(resolved(&engine, utility), None, "{}", utility);
+        }
+    }
+
+    #[test]
+    fn fractions_need_the_fraction_option_and_plain_numbers() {
+        let engine = engine(
+            r#"
+            [generators]
+            "w|width" = { multiplier = 1, unit = "px", fraction = true }
+            "p|padding" = { multiplier = 1, unit = "px" }
+            "#,
+        );
+        assert_eq!(resolved(&engine, "w-1/2"), Some(vec!["width: 50%".to_string()]));
+        assert_eq!(engine.resolve_prefixed("w-1/4", true).map(|(declarations, _)| declarations), None);
+        for utility in ["w--1/2", "w-1/-2", "w-1/0", "w-1e1/2", "p-1/2", "p--4", "p-+4", "p-1e3"] {
+            assert_eq!(resolved(&engine, utility), None, "{}", utility);
+        }
+    }
+
+    #[test]
+    fn arbitrary_values_go_to_the_first_dynamic_entry_of_a_prefix() {
+        let engine = engine(
+            r#"
+            [dynamic]
+            "text|line-height" = { tight = "1.25" }
+            "text|font-size" = { xl = "1.25rem" }
+            "#,
+        );
+        assert_eq!(resolved(&engine, "text-[2rem]"), Some(vec!["line-height: 2rem".to_string()]));
+    }
+
+    #[test]
+    fn variable_multipliers_keep_the_generator_unit() {
+        let engine = engine(
+            r#"
+            [theme]
+            spacing = "4"
+            gap = "0.25rem"
+
+            [generators]
+            "p|padding" = { multiplier = "{spacing}", unit = "px" }
+            "m|margin" = { multiplier = "{gap}", negative = true }
+            "#,
+        );
+        assert_eq!(resolved(&engine, "p-4"), Some(vec!["padding: calc(var(--spacing) * 4px)".to_string()]));
+        assert_eq!(resolved(&engine, "m-4"), Some(vec!["margin: calc(var(--gap) * 4)".to_string()]));
+        assert_eq!(
+            engine.resolve_prefixed("m-2", true).map(|(declarations, _)| declarations),
+            Some(vec!["margin: calc(var(--gap) * -2)".to_string()])
+        );
+    }
+
+    #[test]
+    fn rgba_opacity_converts_theme_colors_behind_variables() {
+        let engine = engine(
+            r##"
+            [theme.colors]
+            red = { 500 = "#ef4444" }
+            danger = "{colors.red.500}"
+
+            [generators]
+            "bg|background-color" = { palette = "colors" }
+
+            [color_opacity]
+            strategy = "rgba"
+            "##,
+        );
+        assert_eq!(resolved(&engine, "bg-red-500"), Some(vec!["background-color: var(--color-red-500)".to_string()]));
+        assert_eq!(resolved(&engine, "bg-red-500/50"), Some(vec!["background-color: rgba(239, 68, 68, 0.5)".to_string()]));
+        assert_eq!(resolved(&engine, "bg-danger/25"), Some(vec!["background-color: rgba(239, 68, 68, 0.25)".to_string()]));
+    }
+
+    #[test]
+    fn shortcuts_skip_the_classes_that_match_no_entry() {
+        let engine = engine(
+            r#"
+            [static]
+            flex = "display: flex;"
+            font-bold = "font-weight: 700;"
+
+            [shortcuts]
+            btn = "flex shadow-xl font-bold"
+            "#,
+        );
+        let rules = engine.generate_css_for_class("btn");
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].declarations, vec!["display: flex".to_string(), "font-weight: 700".to_string()]);
+        assert_eq!(engine.unresolved_shortcut_classes(), vec![("btn", "shadow-xl")]);
+    }
+
+    #[test]
+    fn arbitrary_named_colors_are_colors() {
+        let engine = engine(SHARED_PREFIXES);
+        assert_eq!(resolved(&engine, "text-[red]"), Some(vec!["color: red".to_string()]));
+        assert_eq!(resolved(&engine, "text-[RebeccaPurple]"), Some(vec!["color: RebeccaPurple".to_string()]));
+        assert_eq!(resolved(&engine, "text-[currentColor]"), Some(vec!["color: currentColor".to_string()]));
+        assert_eq!(resolved(&engine, "text-[larger]"), Some(vec!["font-size: larger".to_string()]));
+    }
+
+    #[test]
+    fn shared_prefix_resolves_by_value() {
+        let engine = engine(SHARED_PREFIXES);
+        assert_eq!(entry(&engine, "text-14").as_deref(), Some("[generators] \"text|font-size\""));
+        assert_eq!(entry(&engine, "text-xl").as_deref(), Some("[dynamic] \"text|font-size\""));
+        assert_eq!(entry(&engine, "text-red-500").as_deref(), Some("[generators] \"text|color\""));
+        assert_eq!(resolved(&engine, "text-14"), Some(vec!["font-size: 14px".to_string()]));
+        assert_eq!(resolved(&engine, "text-[#1da1f2]"), Some(vec!["color: #1da1f2".to_string()]));
+        assert_eq!(resolved(&engine, "text-[14px]"), Some(vec!["font-size: 14px".to_string()]));
+    }
+
+    #[test]
+    fn longest_prefix_is_tried_first() {
+        let engine = engine(SHARED_PREFIXES);
+        assert_eq!(
+            resolved(&engine, "rounded-t-[3px]"),
+            Some(vec!["border-top-left-radius: 3px".to_string(), "border-top-right-radius: 3px".to_string()])
+        );
+        assert_eq!(resolved(&engine, "rounded-[3px]"), Some(vec!["border-radius: 3px".to_string()]));
+        assert_eq!(resolved(&engine, "mx-4"), Some(vec!["margin-left: 4px".to_string(), "margin-right: 4px".to_string()]));
+        assert_eq!(resolved(&engine, "m-4"), Some(vec!["margin: 4px".to_string()]));
+    }
+
+    #[test]
+    fn a_shorter_prefix_does_not_read_the_rest_of_a_longer_one() {
+        let engine = engine(SHARED_PREFIXES);
+        // "rounded-t-" doesn't take "sm-x", and "rounded-" doesn't take "t-sm-x".
+        assert_eq!(resolved(&engine, "rounded-t-sm-x"), None);
+        // "mx" doesn't allow negatives, so "-mx-4" isn't read as "-m" with "x-4" either.
+        assert_eq!(engine.resolve_prefixed("mx-4", true), None);
+        assert_eq!(engine.resolve_prefixed("m-4", true).map(|(declarations, _)| declarations), Some(vec!["margin: -4px".to_string()]));
+        assert_eq!(resolved(&engine, "unknown-4"), None);
+    }
 }
@@ -1,17 +1,77 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
-use crate::engine::StyleEngine;
+use crate::engine::{CssRule, Layer, StyleEngine};
 
 pub fn generate_css(class_names: &HashSet<String>, output_path: &Path, engine: &StyleEngine) {
     let mut file = File::create(output_path).unwrap();
     let mut sorted_class_names: Vec<_> = class_names.iter().collect();
     sorted_class_names.sort();
 
-    for cn in sorted_class_names {
-        if let Some(css_rule) = engine.generate_css_for_class(cn) {
-            writeln!(file, "{}", css_rule).unwrap();
+    let mut rules: Vec<_> = sorted_class_names
+        .into_iter()
+        .flat_map(|cn| engine.generate_css_for_class(cn))
+        .collect();
+    // The sort is stable, so rules with equal keys stay in class name order.
+    rules.sort_by_key(CssRule::sort_key);
+
+    // Each keyframes block is emitted once, however many classes use it.
+    let used_keyframes: BTreeSet<&String> = rules.iter().flat_map(|rule| &rule.keyframes).collect();
+    let keyframes: Vec<_> = used_keyframes.into_iter().filter_map(|name| engine.keyframes(name)).collect();
+
+    let used_variables: HashSet<String> = rules
+        .iter()
+        .chain(engine.base_rules())
+        .flat_map(|rule| rule.variables.iter().cloned())
+        .chain(keyframes.iter().flat_map(|keyframes| keyframes.variables.iter().cloned()))
+        .collect();
+
+    writeln!(file, "@layer base, components, utilities;").unwrap();
+    let mut base_blocks: Vec<String> = engine.render_theme_variables(&used_variables).into_iter().collect();
+    base_blocks.extend(engine.base_rules().iter().map(CssRule::render));
+    write_layer(&mut file, "base", &base_blocks);
+    for (layer, name) in [(Layer::Components, "components"), (Layer::Utilities, "utilities")] {
+        let layer_rules: Vec<&CssRule> = rules.iter().filter(|rule| rule.layer == layer).collect();
+        write_layer(&mut file, name, &render_rules(&layer_rules, engine));
+    }
+    for keyframes in keyframes {
+        writeln!(file, "{}", keyframes.render()).unwrap();
+    }
+}
+
+/// Renders sorted rules, collecting the rules of each breakpoint into one
+/// media query emitted after the rules without a breakpoint.
+fn render_rules(rules: &[&CssRule], engine: &StyleEngine) -> Vec<String> {
+    let mut groups: BTreeMap<Option<usize>, Vec<String>> = BTreeMap::new();
+    for rule in rules {
+        groups.entry(rule.breakpoint).or_default().push(rule.render());
+    }
+    let mut blocks = Vec::new();
+    for (breakpoint, rendered) in groups {
+        match breakpoint {
+            Some(index) => blocks.push(format!(
+                "{} {{\n{}\n}}",
+                engine.breakpoint_media_query(index),
+                indent(&rendered.join("\n"))
+            )),
+            None => blocks.extend(rendered),
         }
     }
+    blocks
+}
+
+fn write_layer(file: &mut File, name: &str, blocks: &[String]) {
+    if blocks.is_empty() {
+        return;
+    }
+    writeln!(file, "@layer {} {{", name).unwrap();
+    for block in blocks {
+        writeln!(file, "{}", indent(block)).unwrap();
+    }
+    writeln!(file, "}}").unwrap();
+}
+
+fn indent(block: &str) -> String {
+    block.lines().map(|line| format!("    {}", line)).collect::<Vec<_>>().join("\n")
 }
@@ -0,0 +1,458 @@
+//! `dx inspect`: shows what ended up in a styles.bin, which entry a class
+//! matches, and decompiles the binary back into an equivalent styles.toml.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use colored::Colorize;
+use toml::{Table, Value};
+
+use crate::engine::{self, LoadError, StyleEngine, style_schema};
+use crate::{compiler, resolver, utils};
+
+/// What `dx inspect` prints.
+pub enum Inspect {
+    /// The schema version, the source hash and every entry.
+    Summary,
+    /// The entry a class matches and the CSS it generates.
+    Class(String),
+    /// The binary written back as a styles.toml.
+    Toml,
+}
+
+pub fn run(inspect: &Inspect, config_path: Option<&Path>) {
+    let (engine, label, freshness) = match load(config_path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            // Diagnostics come rendered, with their own "error:" lines.
+            let source = config_path.map_or("the styles built into dx".to_string(), |path| path.display().to_string());
+            println!("{} Failed to load {}:\n{}", "Error:".red(), source, e);
+            return;
+        }
+    };
+    match inspect {
+        Inspect::Summary => print!("{}", summary(&engine, &label, freshness.as_deref())),
+        Inspect::Class(class) => print!("{}", explain(&engine, class)),
+        Inspect::Toml => print!("{}", decompile(&engine, &label)),
+    }
+}
+
+/// Loads the styles.bin of the config as it is, without recompiling it. Also
+/// returns where it was read from and, for a styles.toml, whether the binary
+/// is up to date with it. A styles.toml without a usable styles.bin is
+/// compiled in memory, and nothing is written.
+fn load(config_path: Option<&Path>) -> Result<(StyleEngine, String, Option<String>), String> {
+    let Some(config_path) = config_path else {
+        let engine = StyleEngine::embedded().map_err(|error| error.to_string())?;
+        return Ok((engine, "the styles built into dx".to_string(), None));
+    };
+    if !utils::is_toml_file(config_path) {
+        let engine = StyleEngine::from_file(config_path).map_err(|error| error.to_string())?;
+        return Ok((engine, config_path.display().to_string(), None));
+    }
+
+    let binary_path = engine::binary_path(config_path);
+    let engine = match StyleEngine::from_file(&binary_path) {
+        Ok(engine) => engine,
+        Err(error) => {
+            let config = resolver::resolve(config_path)?;
+            let engine = StyleEngine::from_config(&config).map_err(|error| error.to_string())?;
+            let mut label = format!("{}, compiled in memory and not written", config_path.display());
+            if !matches!(error, LoadError::Missing(_)) {
+                label.push_str(&format!(" since {}", error));
+            }
+            return Ok((engine, label, None));
+        }
+    };
+    let freshness = match resolver::resolve(config_path) {
+        Ok(config) if compiler::source_hash(&config) == engine.source_hash() => {
+            format!("up to date with {}", config_path.display())
+        }
+        Ok(_) => format!("stale, {} has changed since", config_path.display()),
+        Err(_) => format!("{} doesn't resolve", config_path.display()),
+    };
+    Ok((engine, binary_path.display().to_string(), Some(freshness)))
+}
+
+fn summary(engine: &StyleEngine, label: &str, freshness: Option<&str>) -> String {
+    let config = engine.config();
+    let hash = format!("{:016x}", config.source_hash());
+    let mut lines = vec![
+        format!("{:<16}{}", "styles.bin", label),
+        format!("{:<16}{}", "schema version", config.schema_version()),
+        match freshness {
+            Some(freshness) => format!("{:<16}{} ({})", "source hash", hash, freshness),
+            None => format!("{:<16}{}", "source hash", hash),
+        },
+    ];
+
+    let mut dynamics: Vec<_> = config.dynamics().into_iter().flatten().collect();
+    dynamics.sort_by_key(|dynamic| dynamic.order());
+    let mut styles: Vec<_> = config.styles().into_iter().flatten().collect();
+    styles.sort_by_key(|style| style.order());
+    let mut generators: Vec<_> = config.generators().into_iter().flatten().collect();
+    generators.sort_by_key(|generator| generator.order());
+    let dynamic_orders: HashSet<u32> = dynamics.iter().map(|dynamic| dynamic.order()).collect();
+
+    lines.push("\n[static]".to_string());
+    for style in styles.iter().filter(|style| !dynamic_orders.contains(&style.order())) {
+        lines.push(format!(".{} {{ {} }}", style.name(), style.css().unwrap_or_default()));
+    }
+
+    lines.push("\n[dynamic]".to_string());
+    for dynamic in &dynamics {
+        let prefix = dynamic.prefix().unwrap_or_default();
+        let properties: Vec<&str> = dynamic.properties().into_iter().flatten().collect();
+        lines.push(format!("\"{}|{}\"", prefix, properties.join(",")));
+        for style in styles.iter().filter(|style| style.order() == dynamic.order()) {
+            lines.push(format!("    .{} {{ {} }}", style.name(), style.css().unwrap_or_default()));
+        }
+        let arbitrary: Vec<String> = properties.iter().map(|property| format!("{}: <value>;", property)).collect();
+        lines.push(format!("    .{}-[<value>] {{ {} }}", prefix, arbitrary.join(" ")));
+    }
+
+    lines.push("\n[generators]".to_string());
+    for generator in &generators {
+        let value = generator_value(generator);
+        let declarations: Vec<String> = generator
+            .properties()
+            .into_iter()
+            .flatten()
+            .map(|property| format!("{}: {};", property, value))
+            .collect();
+        let input = if generator.palette().is_some() { "<color>" } else { "<n>" };
+        let mut line = format!(".{}-{} {{ {} }}", generator.prefix().unwrap_or_default(), input, declarations.join(" "));
+        let notes = generator_notes(generator);
+        if !notes.is_empty() {
+            line.push_str(&format!("  # {}", notes.join(", ")));
+        }
+        lines.push(line);
+    }
+
+    lines.push("\n[shortcuts]".to_string());
+    for shortcut in config.shortcuts().into_iter().flatten() {
+        let classes: Vec<&str> = shortcut.classes().into_iter().flatten().collect();
+        lines.push(format!(".{} = {}", shortcut.name(), classes.join(" ")));
+    }
+
+    lines.push("\n[variants]".to_string());
+    for variant in config.variants().into_iter().flatten() {
+        lines.push(format!("{}: {}", variant.name(), variant.selector().unwrap_or_default()));
+    }
+    for (index, breakpoint) in config.breakpoints().into_iter().flatten().enumerate() {
+        lines.push(format!("{}: {}", breakpoint.name().unwrap_or_default(), engine.breakpoint_media_query(index)));
+    }
+
+    lines.push("\n[base]".to_string());
+    for rule in config.base().into_iter().flatten() {
+        lines.push(format!("{} {{ {} }}", rule.selector().unwrap_or_default(), rule.css().unwrap_or_default()));
+    }
+
+    lines.push("\n[keyframes]".to_string());
+    for keyframes in config.keyframes().into_iter().flatten() {
+        if let Some(rendered) = engine.keyframes(keyframes.name()) {
+            lines.push(rendered.render());
+        }
+    }
+
+    lines.join("\n") + "\n"
+}
+
+/// The value a generator sets, with "<n>" for the number in the class.
+fn generator_value(generator: &style_schema::Generator<'_>) -> String {
+    if let Some(palette) = generator.palette() {
+        format!("<{} color>", palette)
+    } else if let Some(template) = generator.template() {
+        template.replace("{n}", "<n>")
+    } else if let Some(variable) = generator.variable() {
+        format!("calc(var({}) * <n>{})", variable, generator.unit().unwrap_or_default())
+    } else {
+        format!("<n> * {}{}", generator.multiplier(), generator.unit().unwrap_or_default())
+    }
+}
+
+fn generator_notes(generator: &style_schema::Generator<'_>) -> Vec<String> {
+    let mut notes = Vec::new();
+    if generator.negative() {
+        notes.push("negative".to_string());
+    }
+    if generator.fraction() {
+        notes.push("fraction".to_string());
+    }
+    if generator.template().is_some() {
+        let input = generator.input().variant_name().unwrap_or_default().to_lowercase();
+        notes.push(format!("type = {}", input));
+    }
+    if let Some(min) = generator.min() {
+        notes.push(format!("min = {}", min));
+    }
+    if let Some(max) = generator.max() {
+        notes.push(format!("max = {}", max));
+    }
+    if generator.step() > 0.0 {
+        notes.push(format!("step = {}", generator.step()));
+    }
+    notes
+}
+
+fn explain(engine: &StyleEngine, class: &str) -> String {
+    let Some(entry) = engine.matching_entry(class) else {
+        return format!("{} matches no entry\n", class);
+    };
+    let rendered: Vec<String> = engine
+        .generate_css_for_class(class)
+        .iter()
+        .map(|rule| match rule.breakpoint {
+            Some(index) => format!("{} {{\n{}\n}}", engine.breakpoint_media_query(index), indent(&rule.render())),
+            None => rule.render(),
+        })
+        .collect();
+    format!("{} matches {}\n\n{}\n", class, entry, rendered.join("\n"))
+}
+
+fn indent(block: &str) -> String {
+    block.lines().map(|line| format!("    {}", line)).collect::<Vec<_>>().join("\n")
+}
+
+/// Writes the binary back as a styles.toml that compiles to the same styles.
+/// Values are written as they were compiled, with theme tokens already
+/// replaced by their variables, and the preflight reset (when it was enabled)
+/// becomes part of [base].
+fn decompile(engine: &StyleEngine, label: &str) -> String {
+    let config = engine.config();
+    let mut root = Table::new();
+
+    let variables: Vec<(&str, &str)> = config
+        .variables()
+        .into_iter()
+        .flatten()
+        .map(|variable| (variable.name(), variable.value().unwrap_or_default()))
+        .collect();
+    let variable_values: HashMap<&str, &str> = variables.iter().copied().collect();
+
+    // Palettes name their tokens exactly; the other variables are mapped back
+    // to a token path that gives the same variable name.
+    let mut theme = Table::new();
+    let mut decompiled_variables = HashSet::new();
+    for palette in config.palettes().into_iter().flatten() {
+        let mut group = Table::new();
+        for color in palette.colors().into_iter().flatten() {
+            let variable = compiler::token_variable(&format!("{}.{}", palette.name(), color.name()));
+            let value = variable_values.get(variable.as_str()).copied().or(color.value()).unwrap_or_default();
+            group.insert(color.name().to_string(), Value::String(value.to_string()));
+            decompiled_variables.insert(variable);
+        }
+        theme.insert(palette.name().to_string(), Value::Table(group));
+    }
+    for (name, value) in &variables {
+        if decompiled_variables.contains(*name) {
+            continue;
+        }
+        match token_path(name) {
+            (Some(group), key) => {
+                let group = theme.entry(group).or_insert_with(|| Value::Table(Table::new()));
+                if let Value::Table(group) = group {
+                    group.insert(key, Value::String(value.to_string()));
+                }
+            }
+            (None, key) => {
+                theme.insert(key, Value::String(value.to_string()));
+            }
+        }
+    }
+    root.insert("theme".to_string(), Value::Table(theme));
+
+    let mut css_variables = Table::new();
+    css_variables.insert("enabled".to_string(), Value::Boolean(!variables.is_empty()));
+    css_variables.insert("emit_all".to_string(), Value::Boolean(config.emit_all_variables()));
+    root.insert("css_variables".to_string(), Value::Table(css_variables));
+
+    // Entries are written in their original order, which decides which rule wins.
+    let mut dynamics: Vec<_> = config.dynamics().into_iter().flatten().collect();
+    dynamics.sort_by_key(|dynamic| dynamic.order());
+    let mut styles: Vec<_> = config.styles().into_iter().flatten().collect();
+    styles.sort_by_key(|style| style.order());
+    let mut generators: Vec<_> = config.generators().into_iter().flatten().collect();
+    generators.sort_by_key(|generator| generator.order());
+    let dynamic_orders: HashSet<u32> = dynamics.iter().map(|dynamic| dynamic.order()).collect();
+
+    let mut static_styles = Table::new();
+    for style in styles.iter().filter(|style| !dynamic_orders.contains(&style.order())) {
+        static_styles.insert(style.name().to_string(), Value::String(style.css().unwrap_or_default().to_string()));
+    }
+    root.insert("static".to_string(), Value::Table(static_styles));
+
+    let mut dynamic_entries = Table::new();
+    for dynamic in &dynamics {
+        let prefix = dynamic.prefix().unwrap_or_default();
+        let mut values = Table::new();
+        for style in styles.iter().filter(|style| style.order() == dynamic.order()) {
+            // Every property of the entry has the same value.
+            let declarations = engine::split_declarations(style.css().unwrap_or_default());
+            if let Some(suffix) = style.name().strip_prefix(prefix).and_then(|rest| rest.strip_prefix('-'))
+                && let Some((_, value)) = declarations.first().and_then(|first| first.split_once(':'))
+            {
+                values.insert(suffix.to_string(), Value::String(value.trim().to_string()));
+            }
+        }
+        let properties: Vec<&str> = dynamic.properties().into_iter().flatten().collect();
+        dynamic_entries.insert(format!("{}|{}", prefix, properties.join(",")), Value::Table(values));
+    }
+    root.insert("dynamic".to_string(), Value::Table(dynamic_entries));
+
+    let mut generator_entries = Table::new();
+    for generator in &generators {
+        let properties: Vec<&str> = generator.properties().into_iter().flatten().collect();
+        let key = format!("{}|{}", generator.prefix().unwrap_or_default(), properties.join(","));
+        generator_entries.insert(key, Value::Table(decompile_generator(generator)));
+    }
+    root.insert("generators".to_string(), Value::Table(generator_entries));
+
+    let mut color_opacity = Table::new();
+    let strategy = match config.opacity_strategy() {
+        style_schema::OpacityStrategy::Rgba => "rgba",
+        _ => "color-mix",
+    };
+    color_opacity.insert("strategy".to_string(), Value::String(strategy.to_string()));
+    root.insert("color_opacity".to_string(), Value::Table(color_opacity));
+
+    let mut breakpoints = Table::new();
+    for breakpoint in config.breakpoints().into_iter().flatten() {
+        if let (Some(name), Some(min_width)) = (breakpoint.name(), breakpoint.min_width()) {
+            breakpoints.insert(name.to_string(), Value::String(min_width.to_string()));
+        }
+    }
+    root.insert("breakpoints".to_string(), Value::Table(breakpoints));
+
+    if let Some(dark_mode) = config.dark_mode() {
+        let mut table = Table::new();
+        let strategy = dark_mode.strategy().variant_name().unwrap_or("Media").to_lowercase();
+        table.insert("strategy".to_string(), Value::String(strategy));
+        if let Some(selector) = dark_mode.selector() {
+            table.insert("selector".to_string(), Value::String(selector.to_string()));
+        }
+        root.insert("dark_mode".to_string(), Value::Table(table));
+    }
+
+    let mut variants = Table::new();
+    for variant in config.variants().into_iter().flatten() {
+        variants.insert(variant.name().to_string(), Value::String(variant.selector().unwrap_or_default().to_string()));
+    }
+    root.insert("variants".to_string(), Value::Table(variants));
+
+    let mut shortcuts = Table::new();
+    for shortcut in config.shortcuts().into_iter().flatten() {
+        let classes: Vec<&str> = shortcut.classes().into_iter().flatten().collect();
+        shortcuts.insert(shortcut.name().to_string(), Value::String(classes.join(" ")));
+    }
+    root.insert("shortcuts".to_string(), Value::Table(shortcuts));
+
+    let mut preflight = Table::new();
+    preflight.insert("enabled".to_string(), Value::Boolean(false));
+    root.insert("preflight".to_string(), Value::Table(preflight));
+
+    // A selector used twice (e.g. by the reset and by [base]) becomes one rule,
+    // since [base] has one key per selector. The header says so.
+    let mut base = Table::new();
+    let mut merged_selectors = Vec::new();
+    for rule in config.base().into_iter().flatten() {
+        let (selector, css) = (rule.selector().unwrap_or_default(), rule.css().unwrap_or_default());
+        let css = match base.get(selector).and_then(Value::as_str) {
+            Some(existing) => {
+                merged_selectors.push(selector);
+                format!("{} {}", existing, css)
+            }
+            None => css.to_string(),
+        };
+        base.insert(selector.to_string(), Value::String(css));
+    }
+    root.insert("base".to_string(), Value::Table(base));
+
+    let mut keyframes = Table::new();
+    for block in config.keyframes().into_iter().flatten() {
+        let mut steps = Table::new();
+        for step in block.steps().into_iter().flatten() {
+            let selector = step.selector().unwrap_or_default().to_string();
+            steps.insert(selector, Value::String(step.css().unwrap_or_default().to_string()));
+        }
+        keyframes.insert(block.name().to_string(), Value::Table(steps));
+    }
+    root.insert("keyframes".to_string(), Value::Table(keyframes));
+
+    let mut header = format!(
+        "# Decompiled from {} (schema version {}, source hash {:016x}).\n",
+        label,
+        config.schema_version(),
+        config.source_hash()
+    );
+    if !merged_selectors.is_empty() {
+        header.push_str(&format!(
+            "# Not strictly equivalent: the base rules for {} were merged into one rule\n\
+             # where the first one was, so the later declarations now come before the\n\
+             # base rules that were between them.\n",
+            merged_selectors.join(", ")
+        ));
+    }
+    header.push('\n');
+    header + &toml::to_string(&root).unwrap_or_default()
+}
+
+fn decompile_generator(generator: &style_schema::Generator<'_>) -> Table {
+    let mut table = Table::new();
+    let unit = generator.unit().unwrap_or_default();
+    if let Some(palette) = generator.palette() {
+        table.insert("palette".to_string(), Value::String(palette.to_string()));
+    } else if let Some(template) = generator.template() {
+        table.insert("value".to_string(), Value::String(template.to_string()));
+        let input = generator.input().variant_name().unwrap_or("Number").to_lowercase();
+        table.insert("type".to_string(), Value::String(input));
+    } else if let Some(variable) = generator.variable() {
+        let path = match token_path(variable) {
+            (Some(group), key) => format!("{}.{}", group, key),
+            (None, key) => key,
+        };
+        table.insert("multiplier".to_string(), Value::String(format!("{{{}}}", path)));
+        // Only set when the token is a plain number.
+        if !unit.is_empty() {
+            table.insert("unit".to_string(), Value::String(unit.to_string()));
+        }
+    } else {
+        table.insert("multiplier".to_string(), float(generator.multiplier()));
+        if !unit.is_empty() {
+            table.insert("unit".to_string(), Value::String(unit.to_string()));
+        }
+    }
+    if generator.negative() {
+        table.insert("negative".to_string(), Value::Boolean(true));
+    }
+    if generator.fraction() {
+        table.insert("fraction".to_string(), Value::Boolean(true));
+    }
+    if let Some(min) = generator.min() {
+        table.insert("min".to_string(), float(min));
+    }
+    if let Some(max) = generator.max() {
+        table.insert("max".to_string(), float(max));
+    }
+    if generator.step() > 0.0 {
+        table.insert("step".to_string(), float(generator.step()));
+    }
+    table
+}
+
+/// The theme group and key of a token with the given CSS variable, inverting
+/// compiler::token_variable: "--color-slate-200" -> (colors, "slate-200").
+fn token_path(variable: &str) -> (Option<&'static str>, String) {
+    let name = variable.strip_prefix("--").unwrap_or(variable);
+    for (group, shortened) in [("colors", "color"), ("radii", "radius"), ("fonts", "font"), ("shadows", "shadow")] {
+        if let Some(key) = name.strip_prefix(shortened).and_then(|rest| rest.strip_prefix('-')) {
+            return (Some(group), key.to_string());
+        }
+    }
+    (None, name.to_string())
+}
+
+/// A float written as its shortest form, so 0.1f32 stays "0.1".
+fn float(value: f32) -> Value {
+    Value::Float(value.to_string().parse().unwrap_or(value as f64))
+}
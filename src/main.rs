@@ -1,26 +1,59 @@
 use std::collections::{HashMap, HashSet, VecDeque};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
 use colored::Colorize;
 use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
 
+mod cli;
+mod color;
+mod compiler;
 mod data_manager;
 mod engine;
 mod generator;
+mod inspect;
 mod parser;
+mod resolver;
 mod utils;
+mod validation;
 mod watcher;
 
 fn main() {
-    let style_engine = match engine::StyleEngine::new() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = match cli::parse(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            println!("{} {}", "Error:".red(), e);
+            return;
+        }
+    };
+    let config_path = args.config.or_else(|| std::env::current_dir().ok().and_then(|cwd| utils::find_config(&cwd)));
+    match &args.command {
+        cli::Command::Watch => {}
+        cli::Command::ConfigResolved => {
+            cli::print_resolved_config(config_path.as_deref());
+            return;
+        }
+        cli::Command::Inspect(inspect) => {
+            inspect::run(inspect, config_path.as_deref());
+            return;
+        }
+    }
+
+    let mut style_engine = match engine::StyleEngine::load(config_path.as_deref()) {
         Ok(engine) => engine,
         Err(e) => {
-            println!("{} Failed to initialize StyleEngine: {}. Please run 'cargo build' to generate it.", "Error:".red(), e);
+            println!("{} Failed to initialize StyleEngine:\n{}", "Error:".red(), e);
             return;
         }
     };
     println!("{}", "✅ Dx Styles initialized with new Style Engine.".bold().green());
+    match &config_path {
+        Some(config_path) => println!("Using {}", config_path.display()),
+        None => println!("{}", "No styles.toml found, using the built-in styles.".yellow()),
+    }
+    style_engine.warn_unresolved_shortcut_classes();
+    let toml_path = config_path.as_deref().filter(|path| utils::is_toml_file(path));
 
     let dir = PathBuf::from("src");
     let output_file = PathBuf::from(".").join("styles.css");
@@ -51,14 +84,20 @@ fn main() {
     let config = Config::default().with_poll_interval(Duration::from_millis(50));
     let mut watcher = RecommendedWatcher::new(tx, config).unwrap();
     watcher.watch(&dir, RecursiveMode::Recursive).unwrap();
+    watch_config_files(&mut watcher, toml_path.into_iter().chain(style_engine.config_files().iter().map(PathBuf::as_path)));
 
     let mut event_queue: VecDeque<(PathBuf, bool)> = VecDeque::new();
+    let mut config_changed = false;
 
     loop {
         match rx.recv_timeout(Duration::from_millis(100)) {
             Ok(Ok(event)) => {
                 for path in event.paths {
-                    if utils::is_code_file(&path) {
+                    let is_config_file = toml_path.is_some_and(|toml_path| utils::is_same_file(&path, toml_path))
+                        || style_engine.config_files().iter().any(|file| utils::is_same_file(&path, file));
+                    if is_config_file && !matches!(event.kind, notify::EventKind::Access(_)) {
+                        config_changed = true;
+                    } else if utils::is_code_file(&path) {
                         let is_remove = matches!(event.kind, notify::EventKind::Remove(_));
                         event_queue.push_back((path, is_remove));
                     }
@@ -66,17 +105,24 @@ fn main() {
             }
             Ok(Err(e)) => println!("Watch error: {:?}", e),
             Err(_) => {
+                if config_changed
+                    && let Some(toml_path) = toml_path.filter(|path| path.exists())
+                {
+                    watcher::process_config_change(toml_path, &global_classnames, &output_file, &mut style_engine);
+                    watch_config_files(&mut watcher, style_engine.config_files().iter().map(PathBuf::as_path));
+                    config_changed = false;
+                }
                 let mut processed_paths = HashSet::new();
                 let now = Instant::now();
                 while let Some((path, is_remove)) = event_queue.pop_front() {
                     if processed_paths.contains(&path) {
                         continue;
                     }
-                    if let Some(last_time) = pending_events.get(&path) {
-                        if now.duration_since(*last_time) < Duration::from_millis(100) {
-                            event_queue.push_back((path.clone(), is_remove));
-                            continue;
-                        }
+                    if let Some(last_time) = pending_events.get(&path)
+                        && now.duration_since(*last_time) < Duration::from_millis(100)
+                    {
+                        event_queue.push_back((path.clone(), is_remove));
+                        continue;
                     }
                     if is_remove {
                         watcher::process_file_remove(&path, &mut file_classnames, &mut classname_counts, &mut global_classnames, &output_file, &style_engine);
@@ -90,3 +136,13 @@ fn main() {
         }
     }
 }
+
+/// Watches the directories of styles.toml and the files it extends, so editing
+/// them reloads the styles. The directory rather than the file, so the watch
+/// survives editors that save by replacing it.
+fn watch_config_files<'a>(watcher: &mut RecommendedWatcher, files: impl Iterator<Item = &'a Path>) {
+    for file in files {
+        let directory = file.parent().filter(|directory| !directory.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let _ = watcher.watch(directory, RecursiveMode::NonRecursive);
+    }
+}
@@ -156,16 +156,14 @@ impl ClassNameVisitor {
 
     fn visit_jsx_opening_element(&mut self, elem: &JSXOpeningElement) {
         for attr in &elem.attributes {
-            if let JSXAttributeItem::Attribute(attr) = attr {
-                if let ast::JSXAttributeName::Identifier(ident) = &attr.name {
-                    if ident.name == "className" {
-                        if let Some(ast::JSXAttributeValue::StringLiteral(lit)) = &attr.value {
-                            lit.value.split_whitespace().for_each(|cn| {
-                                self.class_names.insert(cn.to_string());
-                            });
-                        }
-                    }
-                }
+            if let JSXAttributeItem::Attribute(attr) = attr
+                && let ast::JSXAttributeName::Identifier(ident) = &attr.name
+                && ident.name == "className"
+                && let Some(ast::JSXAttributeValue::StringLiteral(lit)) = &attr.value
+            {
+                lit.value.split_whitespace().for_each(|cn| {
+                    self.class_names.insert(cn.to_string());
+                });
             }
         }
     }
@@ -0,0 +1,528 @@
+//! Resolves the `extends` chain of styles.toml into a single config.
+//!
+//! Each file is merged over the files and built-in presets it extends, in
+//! order. Top-level sections and [theme] groups (and [preflight.overrides])
+//! are merged key by key; any other entry (a class, a generator, a variant, a
+//! keyframes block...) is replaced as a whole by a later definition. An
+//! [unset] table removes inherited keys, e.g. `generators = ["z|z-index"]` or
+//! `theme.colors = ["pink"]`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::{fs, io};
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+
+use crate::validation;
+
+/// The built-in presets, extended by name (e.g. `extends = ["colors"]`).
+const PRESETS: &[(&str, &str)] = &[
+    ("colors", include_str!("presets/colors.toml")),
+    ("breakpoints", include_str!("presets/breakpoints.toml")),
+];
+
+/// A file or preset that contributed to the config.
+pub struct Source {
+    /// How the source is shown in diagnostics and the resolved dump.
+    pub name: String,
+    /// The file, for sources that are not built-in presets.
+    pub path: Option<PathBuf>,
+    pub text: String,
+}
+
+pub struct ResolvedConfig {
+    pub table: Table,
+    /// Every source, in the order they were merged (the root file last).
+    pub sources: Vec<Source>,
+    /// The source that defined each leaf value, by key path.
+    origins: BTreeMap<Vec<String>, usize>,
+    /// Whether any source extends or unsets something.
+    layered: bool,
+}
+
+/// Loads a styles.toml and everything it extends. Each file is validated on
+/// its own, then the classes of the merged config are checked across files,
+/// and the errors are returned rendered.
+pub fn resolve(path: &Path) -> Result<ResolvedConfig, String> {
+    let source = read_source(path).map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+    resolve_source(source)
+}
+
+/// Reads a file, named by its canonical path so that the cycle check sees the
+/// same file however it is extended (`a.toml`, `./a.toml`, `../dir/a.toml`).
+fn read_source(path: &Path) -> io::Result<Source> {
+    let path = fs::canonicalize(path)?;
+    let text = fs::read_to_string(&path)?;
+    Ok(Source { name: path.display().to_string(), path: Some(path), text })
+}
+
+/// Resolves a config from the text of its root file.
+pub fn resolve_source(source: Source) -> Result<ResolvedConfig, String> {
+    let mut config = ResolvedConfig { table: Table::new(), sources: Vec::new(), origins: BTreeMap::new(), layered: false };
+    config.load(source, &mut Vec::new())?;
+    if config.layered {
+        config.check_merged()?;
+    }
+    Ok(config)
+}
+
+impl ResolvedConfig {
+    /// The source of a config that doesn't extend or unset anything, which
+    /// can be deserialized directly to keep the locations of its errors.
+    pub fn standalone_source(&self) -> Option<&Source> {
+        match self.sources.as_slice() {
+            [source] if !self.layered => Some(source),
+            _ => None,
+        }
+    }
+
+    /// The files the config was read from.
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.sources.iter().filter_map(|source| source.path.as_deref())
+    }
+
+    /// Writes the merged config as TOML, with the source of every entry in a
+    /// trailing comment.
+    pub fn to_annotated_toml(&self) -> String {
+        let names: Vec<&str> = self.sources.iter().map(|source| source.name.as_str()).collect();
+        let mut output = format!("# Resolved from: {}\n", names.join(", "));
+        for (section, value) in &self.table {
+            let path = vec![section.clone()];
+            match value {
+                Value::Table(table) => self.write_table(&mut output, &path, table),
+                value => output.push_str(&format!("{} = {}{}\n", toml_key(section), value, self.origin_comment(&path))),
+            }
+        }
+        output
+    }
+
+    fn write_table(&self, output: &mut String, path: &[String], table: &Table) {
+        let header: Vec<String> = path.iter().map(|key| toml_key(key)).collect();
+        output.push_str(&format!("\n[{}]\n", header.join(".")));
+        let mut groups = Vec::new();
+        for (key, value) in table {
+            let mut entry_path = path.to_vec();
+            entry_path.push(key.clone());
+            match value {
+                Value::Table(group) if merges_by_key(&entry_path) => groups.push((entry_path, group)),
+                value => output.push_str(&format!("{} = {}{}\n", toml_key(key), value, self.origin_comment(&entry_path))),
+            }
+        }
+        for (group_path, group) in groups {
+            self.write_table(output, &group_path, group);
+        }
+    }
+
+    fn origin_comment(&self, path: &[String]) -> String {
+        let mut names: Vec<&str> = Vec::new();
+        for (_, source) in self.origins.range(path.to_vec()..).take_while(|(leaf, _)| leaf.starts_with(path)) {
+            let name = self.sources[*source].name.as_str();
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        format!(" # {}", names.join(", "))
+    }
+
+    fn load(&mut self, source: Source, chain: &mut Vec<String>) -> Result<(), String> {
+        if chain.contains(&source.name) {
+            chain.push(source.name);
+            return Err(format!("Cyclic extends in styles.toml: {}", chain.join(" -> ")));
+        }
+
+        let diagnostics = validation::validate(&source.text);
+        if !diagnostics.is_empty() {
+            return Err(validation::render(&diagnostics, &source.name, &source.text));
+        }
+        let mut table: Table = toml::from_str(&source.text)
+            .map_err(|error| format!("Failed to parse {}: {}", source.name, error.message()))?;
+
+        chain.push(source.name.clone());
+        if let Some(extends) = table.remove("extends") {
+            self.layered = true;
+            let Value::Array(extends) = extends else {
+                return Err(format!("'extends' in {} must be a list of files or preset names", source.name));
+            };
+            for parent in extends {
+                let Value::String(parent) = parent else {
+                    return Err(format!("'extends' in {} must be a list of files or preset names", source.name));
+                };
+                let parent = self.locate(&parent, &source)?;
+                self.load(parent, chain)?;
+            }
+        }
+        chain.pop();
+
+        if let Some(unset) = table.remove("unset") {
+            self.layered = true;
+            let Value::Table(unset) = unset else {
+                return Err(format!("[unset] in {} must be a table", source.name));
+            };
+            self.unset(&[], &unset, &source.name)?;
+        }
+
+        self.sources.push(source);
+        let index = self.sources.len() - 1;
+        merge(&mut self.table, table, &mut Vec::new(), &mut self.origins, index);
+        Ok(())
+    }
+
+    /// Reports the classes defined twice and the [dynamic] classes a generator
+    /// reads as numbers across sources, which the per-file validation can't
+    /// see. The later of the two definitions is the one reported.
+    fn check_merged(&self) -> Result<(), String> {
+        let section = |name: &str| self.table.get(name).and_then(Value::as_table);
+        let mut diagnostics: BTreeMap<usize, Vec<validation::Diagnostic>> = BTreeMap::new();
+        let mut report = |source: usize, path: &[String], message: String| {
+            let span = validation::key_span(&self.sources[source].text, path);
+            diagnostics.entry(source).or_default().push(validation::Diagnostic::new(message, span));
+        };
+
+        // Every class with its section, source and key path, in merge order.
+        let mut classes = Vec::new();
+        for name in section("static").into_iter().flat_map(Table::keys) {
+            let path = vec!["static".to_string(), name.clone()];
+            classes.push((name.clone(), "[static]", self.origin(&path), path));
+        }
+        for (key, suffixes) in section("dynamic").into_iter().flatten() {
+            let Some((prefix, _)) = key.split_once('|') else { continue };
+            for suffix in suffixes.as_table().into_iter().flat_map(Table::keys) {
+                let path = vec!["dynamic".to_string(), key.clone(), suffix.clone()];
+                classes.push((format!("{}-{}", prefix, suffix), "[dynamic]", self.origin(&path), path));
+            }
+        }
+        classes.sort_by_key(|(_, _, source, _)| *source);
+
+        let mut defined: HashMap<&str, (&str, usize)> = HashMap::new();
+        for (name, section, source, path) in &classes {
+            match defined.get(name.as_str()) {
+                Some((first_section, first_source)) => report(
+                    *source,
+                    path,
+                    format!(
+                        "Class '{}' in {} is already defined in {} of {}",
+                        name, section, first_section, self.sources[*first_source].name
+                    ),
+                ),
+                None => {
+                    defined.insert(name, (section, *source));
+                }
+            }
+        }
+
+        for (key, generator) in section("generators").into_iter().flatten() {
+            let Some((prefix, _)) = key.split_once('|') else { continue };
+            if generator.get("palette").is_some() {
+                continue;
+            }
+            let generator_path = vec!["generators".to_string(), key.clone()];
+            let generator_source = self.origin(&generator_path);
+            for (name, section, source, path) in &classes {
+                let numeric = name
+                    .strip_prefix(prefix)
+                    .and_then(|rest| rest.strip_prefix('-'))
+                    .is_some_and(validation::is_numeric);
+                if *section != "[dynamic]" || !numeric || *source == generator_source {
+                    continue;
+                }
+                if *source > generator_source {
+                    let message = format!(
+                        "Class '{}' in [dynamic] collides with the generator '{}' of {}",
+                        name, key, self.sources[generator_source].name
+                    );
+                    report(*source, path, message);
+                } else {
+                    let message = format!(
+                        "Generator '{}' collides with the class '{}' in [dynamic] of {}",
+                        key, name, self.sources[*source].name
+                    );
+                    report(generator_source, &generator_path, message);
+                }
+            }
+        }
+
+        if diagnostics.is_empty() {
+            return Ok(());
+        }
+        let rendered: Vec<String> = diagnostics
+            .into_iter()
+            .map(|(source, mut diagnostics)| {
+                diagnostics.sort_by_key(|diagnostic| diagnostic.span.as_ref().map(|span| span.start));
+                let source = &self.sources[source];
+                validation::render(&diagnostics, &source.name, &source.text)
+            })
+            .collect();
+        Err(rendered.join("\n\n"))
+    }
+
+    /// The source that defined the entry at a key path.
+    fn origin(&self, path: &[String]) -> usize {
+        self.origins
+            .range(path.to_vec()..)
+            .next()
+            .filter(|(leaf, _)| leaf.starts_with(path))
+            .map_or(self.sources.len() - 1, |(_, source)| *source)
+    }
+
+    /// Finds an extended preset by name or a file relative to the extending one.
+    fn locate(&self, parent: &str, child: &Source) -> Result<Source, String> {
+        if !parent.ends_with(".toml") {
+            let Some((_, text)) = PRESETS.iter().find(|(name, _)| *name == parent) else {
+                let names: Vec<&str> = PRESETS.iter().map(|(name, _)| *name).collect();
+                return Err(format!(
+                    "Unknown preset '{}' extended by {} (expected a .toml file or one of: {})",
+                    parent,
+                    child.name,
+                    names.join(", ")
+                ));
+            };
+            return Ok(Source { name: format!("preset:{}", parent), path: None, text: text.to_string() });
+        }
+
+        let directory = child.path.as_deref().and_then(Path::parent).unwrap_or(Path::new("."));
+        let path = directory.join(parent);
+        read_source(&path)
+            .map_err(|error| format!("Failed to read {} extended by {}: {}", path.display(), child.name, error))
+    }
+
+    fn unset(&mut self, path: &[String], unset: &Table, source: &str) -> Result<(), String> {
+        for (key, value) in unset {
+            let mut key_path = path.to_vec();
+            key_path.push(key.clone());
+            match value {
+                Value::Table(nested) => self.unset(&key_path, nested, source)?,
+                Value::Array(keys) => {
+                    let Some(Value::Table(table)) = lookup(&mut self.table, &key_path) else {
+                        return Err(format!("[unset] {} in {} is not an inherited table", key_path.join("."), source));
+                    };
+                    for removed in keys {
+                        let Value::String(removed) = removed else {
+                            return Err(format!("[unset] {} in {} must list key names", key_path.join("."), source));
+                        };
+                        if table.remove(removed).is_none() {
+                            return Err(format!(
+                                "[unset] {} in {} removes '{}', which is not inherited",
+                                key_path.join("."),
+                                source,
+                                removed
+                            ));
+                        }
+                        let mut removed_path = key_path.clone();
+                        removed_path.push(removed.clone());
+                        self.origins.retain(|leaf, _| !leaf.starts_with(&removed_path));
+                    }
+                }
+                _ => return Err(format!("[unset] {} in {} must list key names", key_path.join("."), source)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the table at a key path is merged key by key rather than replaced.
+fn merges_by_key(path: &[String]) -> bool {
+    path.len() <= 1 || path[0] == "theme" || path == ["preflight", "overrides"]
+}
+
+fn merge(
+    target: &mut Table,
+    source: Table,
+    path: &mut Vec<String>,
+    origins: &mut BTreeMap<Vec<String>, usize>,
+    index: usize,
+) {
+    for (key, value) in source {
+        path.push(key.clone());
+        match (target.get_mut(&key), value) {
+            (Some(Value::Table(existing)), Value::Table(table)) if merges_by_key(path) => {
+                merge(existing, table, path, origins, index);
+            }
+            (_, value) => {
+                origins.retain(|leaf, _| !leaf.starts_with(path));
+                record_origins(&value, path, origins, index);
+                target.insert(key, value);
+            }
+        }
+        path.pop();
+    }
+}
+
+fn record_origins(value: &Value, path: &mut Vec<String>, origins: &mut BTreeMap<Vec<String>, usize>, index: usize) {
+    match value {
+        Value::Table(table) if !table.is_empty() => {
+            for (key, value) in table {
+                path.push(key.clone());
+                record_origins(value, path, origins, index);
+                path.pop();
+            }
+        }
+        _ => {
+            origins.insert(path.clone(), index);
+        }
+    }
+}
+
+fn lookup<'a>(table: &'a mut Table, path: &[String]) -> Option<&'a mut Value> {
+    let (first, rest) = path.split_first()?;
+    let value = table.get_mut(first)?;
+    match rest {
+        [] => Some(value),
+        rest => match value {
+            Value::Table(table) => lookup(table, rest),
+            _ => None,
+        },
+    }
+}
+
+fn toml_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_') {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve_text(text: &str) -> Result<ResolvedConfig, String> {
+        resolve_source(Source { name: "styles.toml".to_string(), path: None, text: text.to_string() })
+    }
+
+    fn table(text: &str) -> Table {
+        toml::from_str(text).unwrap()
+    }
+
+    fn get<'a>(config: &'a ResolvedConfig, path: &[&str]) -> Option<&'a Value> {
+        let (first, rest) = path.split_first()?;
+        rest.iter().try_fold(config.table.get(*first)?, |value, key| value.as_table()?.get(*key))
+    }
+
+    /// A directory of its own for a test that extends files.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dx-resolver-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn sections_merge_key_by_key() {
+        let config = resolve_text("extends = [\"breakpoints\"]\n[breakpoints]\nmd = \"800px\"\n3xl = \"1920px\"").unwrap();
+        assert_eq!(get(&config, &["breakpoints", "sm"]).and_then(Value::as_str), Some("640px"));
+        assert_eq!(get(&config, &["breakpoints", "md"]).and_then(Value::as_str), Some("800px"));
+        assert_eq!(get(&config, &["breakpoints", "3xl"]).and_then(Value::as_str), Some("1920px"));
+        assert!(get(&config, &["extends"]).is_none());
+    }
+
+    #[test]
+    fn theme_groups_merge_key_by_key() {
+        let config = resolve_text("extends = [\"colors\"]\n[theme.colors.slate]\n200 = \"#000000\"\n[theme.colors]\nbrand = \"#1da1f2\"").unwrap();
+        assert_eq!(get(&config, &["theme", "colors", "slate", "200"]).and_then(Value::as_str), Some("#000000"));
+        assert_eq!(get(&config, &["theme", "colors", "slate", "50"]).and_then(Value::as_str), Some("#f8fafc"));
+        assert_eq!(get(&config, &["theme", "colors", "brand"]).and_then(Value::as_str), Some("#1da1f2"));
+        assert_eq!(get(&config, &["theme", "colors", "white"]).and_then(Value::as_str), Some("#ffffff"));
+    }
+
+    #[test]
+    fn entries_are_replaced_as_a_whole() {
+        let mut target = table("[generators]\n\"p|padding\" = { multiplier = 4, negative = true }\n\"m|margin\" = { multiplier = 4 }");
+        let source = table("[generators]\n\"p|padding\" = { multiplier = 2 }");
+        let mut origins = BTreeMap::new();
+        merge(&mut target, source, &mut Vec::new(), &mut origins, 1);
+        assert_eq!(target["generators"]["p|padding"], Value::Table(table("multiplier = 2")));
+        assert_eq!(target["generators"]["m|margin"], Value::Table(table("multiplier = 4")));
+        let p = vec!["generators".to_string(), "p|padding".to_string(), "multiplier".to_string()];
+        assert_eq!(origins.get(&p), Some(&1));
+    }
+
+    #[test]
+    fn the_resolved_dump_names_the_source_of_each_value() {
+        let config = resolve_text("extends = [\"breakpoints\"]\n[breakpoints]\nsm = \"600px\"").unwrap();
+        let annotated = config.to_annotated_toml();
+        assert!(annotated.contains("sm = \"600px\" # styles.toml"), "{}", annotated);
+        assert!(annotated.contains("md = \"768px\" # preset:breakpoints"), "{}", annotated);
+    }
+
+    #[test]
+    fn unset_removes_inherited_keys() {
+        let config = resolve_text("extends = [\"colors\", \"breakpoints\"]\n[unset]\nbreakpoints = [\"2xl\"]\ntheme.colors = [\"slate\"]").unwrap();
+        assert!(get(&config, &["breakpoints", "2xl"]).is_none());
+        assert!(get(&config, &["breakpoints", "xl"]).is_some());
+        assert!(get(&config, &["theme", "colors", "slate"]).is_none());
+        assert!(get(&config, &["theme", "colors", "red"]).is_some());
+        assert!(!config.to_annotated_toml().contains("2xl"));
+    }
+
+    #[test]
+    fn unset_rejects_keys_that_are_not_inherited() {
+        let error = resolve_text("extends = [\"breakpoints\"]\n[unset]\nbreakpoints = [\"3xl\"]").err().unwrap();
+        assert!(error.contains("removes '3xl', which is not inherited"), "{}", error);
+
+        let error = resolve_text("[unset]\ngenerators = [\"p|padding\"]").err().unwrap();
+        assert!(error.contains("[unset] generators in styles.toml is not an inherited table"), "{}", error);
+
+        let error = resolve_text("extends = [\"breakpoints\"]\n[unset]\nbreakpoints = [1]").err().unwrap();
+        assert!(error.contains("must list key names"), "{}", error);
+
+        let error = resolve_text("extends = [\"breakpoints\"]\n[unset]\nbreakpoints = \"sm\"").err().unwrap();
+        assert!(error.contains("must list key names"), "{}", error);
+    }
+
+    #[test]
+    fn unknown_presets_are_reported() {
+        let error = resolve_text("extends = [\"spacing\"]").err().unwrap();
+        assert!(error.contains("Unknown preset 'spacing' extended by styles.toml"), "{}", error);
+    }
+
+    #[test]
+    fn files_are_extended_relative_to_the_extending_file() {
+        let dir = test_dir("relative");
+        fs::create_dir_all(dir.join("design")).unwrap();
+        fs::write(dir.join("design/base.toml"), "[static]\nflex = \"display: flex;\"").unwrap();
+        fs::write(dir.join("styles.toml"), "extends = [\"design/base.toml\"]\n[static]\ngrid = \"display: grid;\"").unwrap();
+        let config = resolve(&dir.join("styles.toml")).unwrap();
+        assert!(get(&config, &["static", "flex"]).is_some());
+        assert!(get(&config, &["static", "grid"]).is_some());
+        assert_eq!(config.files().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn classes_are_checked_across_sources() {
+        let dir = fs::canonicalize(test_dir("merged")).unwrap();
+        let styles = dir.join("styles.toml").display().to_string();
+        let base = dir.join("base.toml").display().to_string();
+        fs::write(
+            dir.join("base.toml"),
+            "[static]\ntext-xl = \"font-size: 1.25rem;\"\n\n[generators]\n\"m|margin\" = { multiplier = 1, unit = \"px\" }",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("styles.toml"),
+            "extends = [\"base.toml\"]\n\n[dynamic]\n\"text|font-size\" = { xl = \"1.5rem\" }\n\"m|margin\" = { 4 = \"1rem\" }",
+        )
+        .unwrap();
+        let error = resolve(&dir.join("styles.toml")).err().unwrap();
+        assert!(error.contains(&format!("Class 'text-xl' in [dynamic] is already defined in [static] of {}", base)), "{}", error);
+        assert!(error.contains(&format!("{}:4:22", styles)), "{}", error);
+        assert!(error.contains(&format!("Class 'm-4' in [dynamic] collides with the generator 'm|margin' of {}", base)), "{}", error);
+        assert!(error.contains(&format!("{}:5:16", styles)), "{}", error);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cyclic_extends_are_reported() {
+        let dir = fs::canonicalize(test_dir("cycle")).unwrap();
+        let (a, b) = (dir.join("a.toml").display().to_string(), dir.join("b.toml").display().to_string());
+        let expected = format!("Cyclic extends in styles.toml: {} -> {} -> {}", a, b, a);
+        let dir_name = dir.file_name().unwrap().to_str().unwrap();
+        for parent in ["a.toml".to_string(), "./a.toml".to_string(), format!("../{}/a.toml", dir_name)] {
+            fs::write(dir.join("a.toml"), "extends = [\"b.toml\"]").unwrap();
+            fs::write(dir.join("b.toml"), format!("extends = [\"{}\"]", parent)).unwrap();
+            assert_eq!(resolve(&dir.join("a.toml")).err(), Some(expected.clone()), "{}", parent);
+            assert_eq!(resolve(&dir.join(".").join("a.toml")).err(), Some(expected.clone()), "{}", parent);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
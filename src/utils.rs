@@ -12,7 +12,39 @@ pub fn find_code_files(dir: &Path) -> Vec<PathBuf> {
 }
 
 pub fn is_code_file(path: &Path) -> bool {
-    path.extension().map_or(false, |ext| ext == "tsx" || ext == "jsx")
+    path.extension().is_some_and(|ext| ext == "tsx" || ext == "jsx")
+}
+
+pub fn is_toml_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "toml")
+}
+
+/// Finds the config of the project dx runs in: the nearest styles.toml, or a
+/// styles.bin without one, in `start` or the directories above it, up to the
+/// root of the git repository.
+pub fn find_config(start: &Path) -> Option<PathBuf> {
+    for directory in start.ancestors() {
+        for name in ["styles.toml", "styles.bin"] {
+            let path = directory.join(name);
+            if path.is_file() {
+                return Some(path.strip_prefix(start).map(Path::to_path_buf).unwrap_or(path));
+            }
+        }
+        if directory.join(".git").exists() {
+            break;
+        }
+    }
+    None
+}
+
+/// Whether two paths name the same file, however the watcher spelled them
+/// (relative or absolute). The file may not exist anymore.
+pub fn is_same_file(path: &Path, other: &Path) -> bool {
+    let canonical_parent = |path: &Path| {
+        let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        parent.canonicalize().ok()
+    };
+    path.file_name() == other.file_name() && canonical_parent(path) == canonical_parent(other)
 }
 
 pub fn log_change(
@@ -43,11 +75,7 @@ pub fn log_change(
         format!("-{}", removed_global).bright_red()
     );
 
-    let time_str = if time_us < 1000 {
-        format!("{}µs", time_us)
-    } else {
-        format!("{}ms", time_us / 1000)
-    };
+    let time_str = format_time(time_us);
 
     println!(
         "{} {} -> {} {} · {}",
@@ -58,3 +86,21 @@ pub fn log_change(
         time_str.yellow()
     );
 }
+
+pub fn log_reload(config_path: &Path, output_path: &Path, time_us: u128) {
+    println!(
+        "{} {} -> {} · {}",
+        "reloaded".bright_green(),
+        config_path.display().to_string().bright_cyan(),
+        output_path.display().to_string().bright_magenta(),
+        format_time(time_us).yellow()
+    );
+}
+
+fn format_time(time_us: u128) -> String {
+    if time_us < 1000 {
+        format!("{}µs", time_us)
+    } else {
+        format!("{}ms", time_us / 1000)
+    }
+}
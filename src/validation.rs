@@ -0,0 +1,365 @@
+//! Checks styles.toml before it is compiled and renders the problems found as
+//! diagnostics pointing at the offending line and column.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use colored::Colorize;
+use toml::de::{DeTable, DeValue};
+use toml::Spanned;
+
+const SECTIONS: &[&str] = &[
+    "extends",
+    "unset",
+    "theme",
+    "css_variables",
+    "static",
+    "dynamic",
+    "generators",
+    "color_opacity",
+    "breakpoints",
+    "dark_mode",
+    "variants",
+    "shortcuts",
+    "preflight",
+    "base",
+    "keyframes",
+];
+
+/// A problem in styles.toml, with the byte range it refers to when known.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Option<Range<usize>>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: Option<Range<usize>>) -> Self {
+        Self { message: message.into(), span }
+    }
+}
+
+/// Reports every malformed key, unknown section, duplicate class name,
+/// [dynamic] class shadowing a [generators] value, variant template without
+/// a '&', invalid property name and non-positive multiplier or step in the
+/// config.
+pub fn validate(source: &str) -> Vec<Diagnostic> {
+    let root = match DeTable::parse(source) {
+        Ok(root) => root,
+        Err(error) => return vec![Diagnostic::new(error.message(), error.span())],
+    };
+    let mut diagnostics = Vec::new();
+    let mut classes: HashMap<String, Range<usize>> = HashMap::new();
+    let mut dynamic_suffixes: HashMap<String, Vec<Spanned<String>>> = HashMap::new();
+
+    for (name, _) in root.get_ref() {
+        if !SECTIONS.contains(&name.get_ref().as_ref()) {
+            diagnostics.push(Diagnostic::new(
+                format!("Unknown section [{}] (expected one of: {})", name.get_ref(), SECTIONS.join(", ")),
+                Some(name.span()),
+            ));
+        }
+    }
+
+    if let Some(styles) = section(&root, "static") {
+        for (name, style) in styles {
+            define_class(name.get_ref(), name.span(), "[static]", source, &mut classes, &mut diagnostics);
+            if let Some(declarations) = style.get_ref().as_table() {
+                check_properties(declarations, &mut diagnostics);
+            }
+        }
+    }
+
+    if let Some(dynamic) = section(&root, "dynamic") {
+        for (key, values) in dynamic {
+            let Some(prefix) = check_key(key, "[dynamic]", &mut diagnostics) else {
+                continue;
+            };
+            for (suffix, _) in values.get_ref().as_table().into_iter().flatten() {
+                let name = format!("{}-{}", prefix, suffix.get_ref());
+                define_class(&name, suffix.span(), "[dynamic]", source, &mut classes, &mut diagnostics);
+                let suffixes = dynamic_suffixes.entry(prefix.to_string()).or_default();
+                suffixes.push(Spanned::new(suffix.span(), suffix.get_ref().to_string()));
+            }
+        }
+    }
+
+    if let Some(generators) = section(&root, "generators") {
+        for (key, config) in generators {
+            let Some(prefix) = check_key(key, "[generators]", &mut diagnostics) else {
+                continue;
+            };
+            let config = config.get_ref().as_table();
+            let field = |name: &str| config.and_then(|config| config.get(name));
+            // A [dynamic] entry may share the prefix of a generator ("text-xl"
+            // and "text-14"), but not define a class the generator also reads
+            // as a number. Color generators only match colors.
+            if field("palette").is_none() {
+                let suffixes = dynamic_suffixes.get(prefix).into_iter().flatten();
+                for suffix in suffixes.filter(|suffix| is_numeric(suffix.get_ref())) {
+                    let (line, column) = line_column(source, key.span().start);
+                    diagnostics.push(Diagnostic::new(
+                        format!(
+                            "Class '{}-{}' in [dynamic] collides with the generator '{}' at line {}, column {}",
+                            prefix,
+                            suffix.get_ref(),
+                            key.get_ref(),
+                            line,
+                            column
+                        ),
+                        Some(suffix.span()),
+                    ));
+                }
+            }
+            for name in ["multiplier", "step"] {
+                if let Some(value) = field(name)
+                    && number(value.get_ref()).is_some_and(|number| number <= 0.0)
+                {
+                    diagnostics.push(Diagnostic::new(
+                        format!("The {} of '{}' must be positive", name, key.get_ref()),
+                        Some(value.span()),
+                    ));
+                }
+            }
+        }
+    }
+
+    if let Some(variants) = section(&root, "variants") {
+        for (name, template) in variants {
+            if template.get_ref().as_str().is_some_and(|template| !template.contains('&')) {
+                diagnostics.push(Diagnostic::new(
+                    format!("Variant '{}' must have a '&' where the class goes, e.g. \"&:hover\"", name.get_ref()),
+                    Some(template.span()),
+                ));
+            }
+        }
+    }
+
+    if let Some(base) = section(&root, "base") {
+        for (_, style) in base {
+            if let Some(declarations) = style.get_ref().as_table() {
+                check_properties(declarations, &mut diagnostics);
+            }
+        }
+    }
+    if let Some(overrides) = section(&root, "preflight").and_then(|preflight| preflight.get("overrides")) {
+        for (_, declarations) in overrides.get_ref().as_table().into_iter().flatten() {
+            if let Some(declarations) = declarations.get_ref().as_table() {
+                check_properties(declarations, &mut diagnostics);
+            }
+        }
+    }
+    if let Some(keyframes) = section(&root, "keyframes") {
+        for (_, steps) in keyframes {
+            for (_, step) in steps.get_ref().as_table().into_iter().flatten() {
+                if let Some(declarations) = step.get_ref().as_table() {
+                    check_properties(declarations, &mut diagnostics);
+                }
+            }
+        }
+    }
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.as_ref().map(|span| span.start));
+    diagnostics
+}
+
+/// Renders diagnostics like compiler errors: the message, the location and
+/// the source line with the span underlined.
+pub fn render(diagnostics: &[Diagnostic], path: &str, source: &str) -> String {
+    let mut rendered = Vec::new();
+    for diagnostic in diagnostics {
+        let mut lines = vec![format!("{}{}", "error: ".red().bold(), diagnostic.message.bold())];
+        match &diagnostic.span {
+            Some(span) => {
+                let (line, column) = line_column(source, span.start);
+                let text = source.lines().nth(line - 1).unwrap_or("");
+                let gutter = " ".repeat(line.to_string().len());
+                // Spans over several lines are underlined up to the end of the first.
+                let width = source[span.clone()].lines().next().map_or(1, |first| first.chars().count()).max(1);
+                lines.push(format!("{}{} {}:{}:{}", gutter, "-->".blue().bold(), path, line, column));
+                lines.push(format!("{} {}", gutter, "|".blue().bold()));
+                lines.push(format!("{} {} {}", line.to_string().blue().bold(), "|".blue().bold(), text));
+                lines.push(format!(
+                    "{} {} {}{}",
+                    gutter,
+                    "|".blue().bold(),
+                    " ".repeat(column - 1),
+                    "^".repeat(width).red().bold()
+                ));
+            }
+            None => lines.push(format!(" {} {}", "-->".blue().bold(), path)),
+        }
+        rendered.push(lines.join("\n"));
+    }
+    rendered.join("\n\n")
+}
+
+/// The span of the last key of a path through the tables of a config, e.g.
+/// ["dynamic", "text|font-size", "xl"].
+pub fn key_span(source: &str, path: &[String]) -> Option<Range<usize>> {
+    let root = DeTable::parse(source).ok()?;
+    let (last, parents) = path.split_last()?;
+    let mut table = root.get_ref();
+    for key in parents {
+        table = table.get(key.as_str())?.get_ref().as_table()?;
+    }
+    table.get_key_value(last.as_str()).map(|(key, _)| key.span())
+}
+
+/// Accepts custom properties ("--name") and lowercase, hyphenated property
+/// names with an optional vendor prefix ("-webkit-line-clamp").
+pub fn is_valid_property_name(property: &str) -> bool {
+    if let Some(name) = property.strip_prefix("--") {
+        return !name.is_empty()
+            && name.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_');
+    }
+    let name = property.strip_prefix('-').unwrap_or(property);
+    !name.is_empty()
+        && name.split('-').all(|part| !part.is_empty() && part.chars().all(|ch| ch.is_ascii_lowercase()))
+}
+
+fn section<'a, 'i>(root: &'a Spanned<DeTable<'i>>, name: &str) -> Option<&'a DeTable<'i>> {
+    root.get_ref().get(name).and_then(|value| value.get_ref().as_table())
+}
+
+/// Checks a "prefix|property,property" key and returns its prefix.
+fn check_key<'a>(key: &'a Spanned<std::borrow::Cow<'_, str>>, section: &str, diagnostics: &mut Vec<Diagnostic>) -> Option<&'a str> {
+    let Some((prefix, properties)) = key.get_ref().split_once('|').filter(|(_, properties)| !properties.contains('|'))
+    else {
+        diagnostics.push(Diagnostic::new(
+            format!("Key '{}' in {} must be written \"prefix|property\"", key.get_ref(), section),
+            Some(key.span()),
+        ));
+        return None;
+    };
+    if prefix.is_empty() {
+        diagnostics.push(Diagnostic::new(format!("Key '{}' in {} has an empty prefix", key.get_ref(), section), Some(key.span())));
+        return None;
+    }
+    for property in properties.split(',').map(str::trim) {
+        if !is_valid_property_name(property) {
+            diagnostics.push(Diagnostic::new(
+                format!("Invalid CSS property name '{}' in {}", property, section),
+                Some(key.span()),
+            ));
+        }
+    }
+    Some(prefix)
+}
+
+fn check_properties(declarations: &DeTable<'_>, diagnostics: &mut Vec<Diagnostic>) {
+    for (property, _) in declarations {
+        if !is_valid_property_name(property.get_ref()) {
+            diagnostics.push(Diagnostic::new(
+                format!("Invalid CSS property name '{}'", property.get_ref()),
+                Some(property.span()),
+            ));
+        }
+    }
+}
+
+fn define_class(
+    name: &str,
+    span: Range<usize>,
+    section: &str,
+    source: &str,
+    classes: &mut HashMap<String, Range<usize>>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if let Some(first) = classes.get(name) {
+        let (line, column) = line_column(source, first.start);
+        diagnostics.push(Diagnostic::new(
+            format!("Class '{}' in {} is already defined at line {}, column {}", name, section, line, column),
+            Some(span),
+        ));
+    } else {
+        classes.insert(name.to_string(), span);
+    }
+}
+
+/// Whether a class suffix is a number or fraction, as read by generators.
+pub fn is_numeric(suffix: &str) -> bool {
+    !suffix.is_empty() && suffix.chars().all(|ch| ch.is_ascii_digit() || ch == '.' || ch == '/')
+}
+
+fn number(value: &DeValue<'_>) -> Option<f64> {
+    if let Some(integer) = value.as_integer() {
+        return i64::from_str_radix(&integer.as_str().replace('_', ""), integer.radix()).ok().map(|n| n as f64);
+    }
+    value.as_float()?.as_str().replace('_', "").parse().ok()
+}
+
+/// The 1-based line and column of a byte offset.
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |text| text.chars().count()) + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(source: &str) -> Vec<String> {
+        validate(source).into_iter().map(|diagnostic| diagnostic.message).collect()
+    }
+
+    #[test]
+    fn line_column_counts_characters_from_one() {
+        let source = "[static]\nflex = 3\n\"é\" = 4";
+        assert_eq!(line_column(source, 0), (1, 1));
+        assert_eq!(line_column(source, source.find("3").unwrap()), (2, 8));
+        assert_eq!(line_column(source, source.find("4").unwrap()), (3, 7));
+    }
+
+    #[test]
+    fn render_underlines_the_span() {
+        colored::control::set_override(false);
+        let source = "[static]\nflex = 3\n";
+        let start = source.find("flex").unwrap();
+        let diagnostics = [Diagnostic::new("Bad class", Some(start..start + 4))];
+        assert_eq!(
+            render(&diagnostics, "styles.toml", source),
+            "error: Bad class\n --> styles.toml:2:1\n  |\n2 | flex = 3\n  | ^^^^"
+        );
+        let diagnostics = [Diagnostic::new("Bad config", None)];
+        assert_eq!(render(&diagnostics, "styles.toml", source), "error: Bad config\n --> styles.toml");
+    }
+
+    #[test]
+    fn duplicate_classes_are_reported_at_the_second_definition() {
+        let source = "[static]\ntext-xl = \"font-size: 1.25rem;\"\n\n[dynamic]\n\"text|font-size\" = { xl = \"1.5rem\" }";
+        let diagnostics = validate(source);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "Class 'text-xl' in [dynamic] is already defined at line 2, column 1");
+        assert_eq!(diagnostics[0].span.clone().map(|span| &source[span]), Some("xl"));
+    }
+
+    #[test]
+    fn numeric_dynamic_classes_collide_with_generators() {
+        let source = "[dynamic]\n\"m|margin\" = { 4 = \"1rem\", auto = \"auto\" }\n\"bg|background\" = { 1 = \"none\" }\n\n[generators]\n\"m|margin\" = { multiplier = 1, unit = \"px\" }\n\"bg|background-color\" = { palette = \"colors\" }";
+        assert_eq!(
+            messages(source),
+            vec!["Class 'm-4' in [dynamic] collides with the generator 'm|margin' at line 6, column 1"]
+        );
+    }
+
+    #[test]
+    fn multipliers_and_steps_must_be_positive() {
+        let source = "[generators]\n\"m|margin\" = { multiplier = 0 }\n\"p|padding\" = { multiplier = 1, step = -0.5 }\n\"w|width\" = { multiplier = 0.25, step = 1_0 }";
+        assert_eq!(
+            messages(source),
+            vec!["The multiplier of 'm|margin' must be positive", "The step of 'p|padding' must be positive"]
+        );
+    }
+
+    #[test]
+    fn unknown_sections_are_reported() {
+        let source = "[static]\nflex = \"display: flex;\"\n\n[statics]\ngrid = \"display: grid;\"";
+        let diagnostics = validate(source);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.starts_with("Unknown section [statics] (expected one of: extends, unset,"));
+        assert_eq!(diagnostics[0].span.clone().map(|span| &source[span]), Some("statics"));
+    }
+}
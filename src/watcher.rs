@@ -3,6 +3,7 @@ use std::path::{Path, PathBuf};
 use crate::{data_manager, generator, parser, utils};
 use crate::engine::StyleEngine;
 use std::time::Instant;
+use colored::Colorize;
 
 pub fn process_file_change(
     path: &Path,
@@ -50,3 +51,30 @@ pub fn process_file_remove(
         utils::log_change(path, 0, old_classnames.len(), output_file, 0, removed_in_global, time_us);
     }
 }
+
+/// Rebuilds the engine from the changed config and regenerates the CSS. The
+/// current engine is kept when the config can't be compiled.
+pub fn process_config_change(
+    config_path: &Path,
+    global_classnames: &HashSet<String>,
+    output_file: &Path,
+    engine: &mut StyleEngine,
+) {
+    let start = Instant::now();
+    match StyleEngine::from_toml(config_path) {
+        // Saving without changes (or undoing a change) leaves the styles as they are.
+        Ok(new_engine) if new_engine.source_hash() == engine.source_hash() => {}
+        Ok(new_engine) => {
+            *engine = new_engine;
+            generator::generate_css(global_classnames, output_file, engine);
+            utils::log_reload(config_path, output_file, start.elapsed().as_micros());
+            engine.warn_unresolved_shortcut_classes();
+        }
+        Err(e) => println!(
+            "{} Failed to reload {}, keeping the previous styles:\n{}",
+            "Error:".red(),
+            config_path.display(),
+            e
+        ),
+    }
+}